@@ -1,6 +1,6 @@
 use std::{sync::Mutex, ptr::addr_of_mut, sync::atomic::{self, AtomicBool}};
 use dynasmrt::{dynasm, DynasmApi, AssemblyOffset};
-use log::debug;
+use log::{debug, warn};
 use region::Protection;
 use anyhow::Result;
 use lazy_static::lazy_static;
@@ -35,6 +35,9 @@ pub static SQ_DEBUGGER: Mutex<Option<dbg::SqDebugger>> = Mutex::new(None);
 
 pub static PRINTF_HOOK_ACTIVE: AtomicBool = AtomicBool::new(true);
 
+/// If set, vm print output is appended here instead of being logged
+pub static PRINT_CAPTURE: Mutex<Option<String>> = Mutex::new(None);
+
 pub fn fixup_addr(offset: usize) -> usize {
     *BASE_ADDR + offset + BASE_OFFSET
 }
@@ -137,6 +140,12 @@ gen_hook! {
     }
     inner {
         unsafe extern "stdcall" fn _print(s: *mut u8) {
+            if let Some(out) = PRINT_CAPTURE.lock().unwrap().as_mut() {
+                let s = std::ffi::CStr::from_ptr(s as *const std::ffi::c_char);
+                out.push_str(&s.to_string_lossy());
+                return;
+            }
+
             if PRINTF_HOOK_ACTIVE.load(atomic::Ordering::Relaxed) {
                 let len = libc::strlen(s as *const std::ffi::c_char);
                 let sl = std::slice::from_raw_parts(s, len);
@@ -193,7 +202,15 @@ gen_hook! {
         // It might be possible to move this code to vm init hook,
         // but for some reason it isn`t working properly, maybe several vm threads involved
         unsafe extern "stdcall" fn bind() { 
-            let mut vm = Vm::from_handle(SQVM_PTR as _).into_safe();
+            let vm = Vm::from_handle(SQVM_PTR as _);
+
+            // Do not clobber hook of debugger attached on previous call
+            if dbg::SqDebugger::is_attached(&vm) {
+                warn!("debugger is already attached to vm, skipping");
+                return;
+            }
+
+            let mut vm = vm.into_safe();
 
             vm.register_closure("TestClos", Box::new(|_vm| {
                 debug!("Called closure");
@@ -272,6 +289,24 @@ fn register_test_functions(vm: &mut Vm<safety::Safe>) {
         a.into_iter().sum()
     }
     
+    vm.register_function("TestResult", test_result);
+    #[sqfn]
+    fn test_result(a: isize) -> Result<isize, String> {
+        if a < 0 {
+            return Err(format!("expected non-negative, got {a}"));
+        }
+        Ok(a * 2)
+    }
+
+    vm.register_function("TestUnitResult", test_unit_result);
+    #[sqfn]
+    fn test_unit_result(a: isize) -> Result<(), String> {
+        if a < 0 {
+            return Err(format!("expected non-negative, got {a}"));
+        }
+        Ok(())
+    }
+
     vm.register_function("TestVarargs", test_varargs);
     #[sqfn(varargs = "varargs")]
     fn test_varargs(_norm: DynSqVar) -> isize {
@@ -292,6 +327,14 @@ fn register_test_functions(vm: &mut Vm<safety::Safe>) {
         out
     }
     
+    // e.g. `local t = {}; t.me <- t; TestCycle(t)`
+    vm.register_function("TestCycle", test_cycle);
+    #[sqfn]
+    fn test_cycle(input: DynSqVar) -> String {
+        debug!("self-referencing value: {input}");
+        input.to_string()
+    }
+    
     vm.register_function("TestCreateUserData", test_create_userdata);
     #[sqfn]
     fn test_create_userdata() -> SqUserData {
@@ -362,4 +405,179 @@ fn register_test_functions(vm: &mut Vm<safety::Safe>) {
         obj
     }
 
+    // e.g. `TestReadUserPointer(TestUserPointer())`, pointer to static is valid for any script
+    static USER_POINTER_TARGET: isize = 1337;
+    vm.register_function("TestUserPointer", test_user_pointer);
+    #[sqfn]
+    fn test_user_pointer() -> SqUserPointer<isize> {
+        &USER_POINTER_TARGET as *const isize as *mut isize
+    }
+
+    vm.register_function("TestReadUserPointer", test_read_user_pointer);
+    #[sqfn]
+    fn test_read_user_pointer(ptr: SqUserPointer<isize>) -> Result<isize, String> {
+        if !std::ptr::eq(ptr, &USER_POINTER_TARGET) {
+            return Err(format!("unknown pointer {ptr:p}"));
+        }
+        // SAFETY: pointer is checked to be address of static above
+        Ok(unsafe { *ptr })
+    }
+
+    // e.g. `function f(a) { local b = 1; return TestLocalsOnce(); }`, true if each local of caller is read once
+    vm.register_function("TestLocalsOnce", test_locals_once);
+    #[sqfn(vm_var = "vm")]
+    fn test_locals_once() -> bool {
+        let locals = vm.get_frame_locals(1, Some(0));
+        let mut names: Vec<_> = locals.iter().map(|l| l.name.as_str()).collect();
+        debug!("caller locals: {names:?}");
+        names.sort_unstable();
+        names.dedup();
+        names.len() == locals.len()
+    }
+
+    // e.g. `TestStringRoundTrip()`, true if owned and borrowed strings with non-ascii 
+    // and zero bytes are read back unchanged
+    vm.register_function("TestStringRoundTrip", test_string_round_trip);
+    #[sqfn]
+    fn test_string_round_trip() -> bool {
+        let vm = Vm::open(1024);
+        ["plain", "", "ünïcödé ключ 鍵 🦀", "zero\0inside\0"].into_iter().all(|s| {
+            vm.push(s);
+            vm.push(s.to_string());
+            let owned: Result<String, _> = vm.get(-1);
+            let borrowed: Result<String, _> = vm.get(-2);
+            vm.pop(2);
+            debug!("pushed {s:?}, read {owned:?} and {borrowed:?}");
+            matches!((owned, borrowed), (Ok(o), Ok(b)) if o == s && b == s)
+        })
+    }
+
+    // e.g. `TestFloatRoundTrip()`, true if floats pushed to vm are read back unchanged
+    vm.register_function("TestFloatRoundTrip", test_float_round_trip);
+    #[sqfn]
+    fn test_float_round_trip() -> bool {
+        let vm = Vm::open(1024);
+        [0.0, -0.0, 1.5, -273.15, SqFloat::MAX, SqFloat::MIN_POSITIVE, SqFloat::INFINITY].into_iter().all(|f| {
+            vm.push(f);
+            let read: Result<SqFloat, _> = vm.get(-1);
+            vm.pop(1);
+            debug!("pushed {f:?}, read {read:?}");
+            matches!(read, Ok(r) if r.to_bits() == f.to_bits())
+        })
+    }
+
+    // e.g. `TestBoolRoundTrip()`, true if bools pushed to vm are read back unchanged
+    // and any nonzero `SQBool` is read as true
+    vm.register_function("TestBoolRoundTrip", test_bool_round_trip);
+    #[sqfn]
+    fn test_bool_round_trip() -> bool {
+        let vm = Vm::open(1024);
+        let pushed = [true, false].into_iter().all(|b| {
+            vm.push(b);
+            let read: Result<bool, _> = vm.get(-1);
+            vm.pop(1);
+            debug!("pushed {b}, read {read:?}");
+            matches!(read, Ok(r) if r == b)
+        });
+
+        // Scripts only produce 0 and 1, so bool with other value is forged from handler
+        vm.push(true);
+        let nonzero = match vm.get_stack_obj(-1) {
+            Ok(mut obj) => {
+                vm.pop(1);
+                obj._unVal.nInteger = 2;
+                vm.push_stack_obj(&obj);
+                let read: Result<bool, _> = vm.get(-1);
+                vm.pop(1);
+                debug!("pushed bool with value 2, read {read:?}");
+                matches!(read, Ok(true))
+            }
+            Err(e) => {
+                vm.pop(1);
+                debug!("failed to get bool handler: {e}");
+                false
+            }
+        };
+
+        pushed && nonzero
+    }
+
+    // e.g. `TestFunctionProtos()`, true if call site and line info of compiled function are read
+    vm.register_function("TestFunctionProtos", test_function_protos);
+    #[sqfn]
+    fn test_function_protos() -> bool {
+        let vm = Vm::open(1024);
+        let src = "function Callee(a) {\n  return a;\n}\nfunction Caller() {\n  local x = 1;\n  return Callee(x) + 1;\n}\n";
+        let ran = vm.compile_closure(src.into(), "protos.nut".into()).is_ok() && {
+            vm.push_root_table();
+            let ok = vm.closure_call(1, None).is_ok();
+            vm.pop(1);
+            ok
+        };
+
+        let protos = match vm.get_function_protos() {
+            Ok(protos) if ran => protos,
+            res => {
+                debug!("failed to read protos: {:?}", res.err());
+                return false;
+            }
+        };
+        debug!("protos: {protos:?}");
+
+        protos.iter().any(|proto| proto.name.as_deref() == Some("Caller")
+            && proto.src.as_deref() == Some("protos.nut")
+            && proto.calls == [("Callee".to_string(), Some(6))]
+            && proto.lines.windows(2).all(|w| w[0].0 <= w[1].0)
+        )
+    }
+
+    // e.g. `TestDebuggerRegistry()`, true if hook set before debugger is still called
+    // and vm stays registered until debugger attached last is dropped
+    vm.register_function("TestDebuggerRegistry", test_debugger_registry);
+    #[sqfn]
+    fn test_debugger_registry() -> bool {
+        use std::sync::{Arc, atomic::AtomicUsize};
+
+        let mut vm = Vm::open(1024);
+        let handle = vm.api().handle();
+        vm.api().enable_debug_info(true);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_hook = calls.clone();
+        vm.set_debug_hook(move |_, _| {
+            calls_hook.fetch_add(1, atomic::Ordering::Relaxed);
+        });
+
+        let first = dbg::SqDebugger::attach(vm);
+        // SAFETY: vm is closed only by the second debugger
+        let second = dbg::SqDebugger::attach(unsafe { Vm::from_handle(handle).into_safe() });
+        std::mem::forget(first.detach());
+
+        // SAFETY: friend vm is not closed on drop
+        let vm = unsafe { Vm::from_handle(handle).into_friend() };
+        let registered = dbg::SqDebugger::is_attached(&vm);
+
+        second.resume();
+        let ran = vm.compile_closure("local a = 1;".into(), "registry.nut".into()).is_ok() && {
+            vm.push_root_table();
+            let ok = vm.closure_call(1, None).is_ok();
+            vm.pop(1);
+            ok
+        };
+        let chained = calls.load(atomic::Ordering::Relaxed) > 0;
+
+        drop(second);
+        let unregistered = !dbg::SqDebugger::is_attached(&vm);
+        debug!("registered: {registered}, ran: {ran}, chained: {chained}, unregistered: {unregistered}");
+        registered && ran && chained && unregistered
+    }
+
+    // e.g. `function g() { local a = 5; yield a; } local gen = g(); resume gen; TestGenerator(gen)`
+    vm.register_function("TestGenerator", test_generator);
+    #[sqfn]
+    fn test_generator(input: DynSqVar) -> String {
+        debug!("generator: {input}");
+        input.to_string()
+    }
+
 }
\ No newline at end of file
@@ -1,24 +1,104 @@
-use sq_common::{*, dbg::{SqLocalVarWithLvl, SqBreakpoint}, vm::{SqLocalVar, DebugEvent, DebugEventWithSrc}};
+use sq_common::{*, dbg::{SqLocalVarWithLvl, SqBreakpoint, SqWatchpoint, WatchpointEvent}, vm::{SqLocalVar, SqStackInfo, SqPathKey, DebugEvent, DebugEventWithSrc}};
 use std::{
     sync::{atomic, Mutex, Arc, RwLock},
     fs::{File, read_dir}, rc::Rc,
-    io::Read, path::Path, ops::Range, time::Duration, 
+    io::Read, path::Path, ops::Range, time::{Duration, Instant}, 
+    collections::VecDeque, fmt::Write,
 };
 use clap::{Subcommand, Command, FromArgMatches};
-use anyhow::{Result, bail};
+use anyhow::{Result, bail, anyhow};
 use logos::Logos;
+use crossbeam::channel::Receiver;
 use serde::{Serialize, Deserialize};
 use crate::hooks;
 
 
 const DEFAULT_STATE_FILENAME: &str = "state.json";
+/// Commands executed at startup, searched in working and home directory
+const CONFIG_FILENAME: &str = ".suba-dbg";
+/// Entered commands, stored in home directory
+const HISTORY_FILENAME: &str = ".suba-dbg-history";
+const DEFAULT_MAX_BUFFERS: usize = 256;
+/// Editor of buffers, if not set and not found in environment
+const DEFAULT_EDITOR: &str = "hx";
+/// Max count of values visited by `find`
+const FIND_MAX_NODES: usize = 100_000;
+/// Max length of traced call argument value
+const TRACE_ARG_MAX_LEN: usize = 40;
+/// Count of last received debug events kept in log
+const EVENT_LOG_SIZE: usize = 10_000;
+/// Integers closer than this to `SQInteger` bounds are reported as possible overflow
+const OVERFLOW_MARGIN: isize = 1024;
+/// Default step limit of `step --while-not`
+const STEP_WHILE_MAX: usize = 1000;
+/// Time to wait for vm to halt after each step of `step --while-not`
+const STEP_WHILE_WAIT_MS: u64 = 2000;
+
+/// Warn about integers near `SQInteger` bounds in examined and evaluated values
+static OVERFLOW_WARN: atomic::AtomicBool = atomic::AtomicBool::new(false);
+
+/// Formats of recorded trace file
+#[derive(clap::ValueEnum, Copy, Clone, Debug)]
+enum TraceFormat {
+    /// Chrome Tracing JSON, loadable in chrome://tracing or Perfetto
+    Chrome,
+}
+
+/// Kinds of traced debug events
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum TraceKind {
+    Calls,
+    Returns,
+    Lines,
+}
+
+impl TraceKind {
+    fn of(event: &DebugEvent) -> Self {
+        match event {
+            DebugEvent::FnCall(..) => Self::Calls,
+            DebugEvent::FnRet(..) => Self::Returns,
+            DebugEvent::Line(_) => Self::Lines,
+        }
+    }
+}
 
 #[derive(clap::ValueEnum, Copy, Clone, Debug)]
 enum BoolVal {
+    #[value(alias = "on")]
     True,
+    #[value(alias = "off")]
     False,
 }
 
+/// Format of backtrace, locals and examined values
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Rendering of integers in examined values
+#[derive(clap::ValueEnum, Copy, Clone, Debug)]
+enum IntFormat {
+    Dec,
+    Hex,
+    Bin,
+    Oct,
+    Char,
+}
+
+impl From<IntFormat> for SqIntFormat {
+    fn from(value: IntFormat) -> Self {
+        match value {
+            IntFormat::Dec => SqIntFormat::Dec,
+            IntFormat::Hex => SqIntFormat::Hex,
+            IntFormat::Bin => SqIntFormat::Bin,
+            IntFormat::Oct => SqIntFormat::Oct,
+            IntFormat::Char => SqIntFormat::Char,
+        }
+    }
+}
+
 impl From<BoolVal> for bool {
     fn from(value: BoolVal) -> Self {
         match value {
@@ -34,7 +114,75 @@ enum SetCommands {
     PrintfHook {
         #[arg(value_enum)]
         active: BoolVal,
-    }
+    },
+
+    /// Set max count of script buffers.
+    /// When exceeded, oldest never edited buffer will be evicted
+    MaxBuffers {
+        count: usize,
+    },
+
+    /// Warn when examined or evaluated integer is near `SQInteger` bounds,
+    /// what may be caused by wraparound. Heuristic, values are not altered
+    OverflowWarn {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Generate full line info for scripts compiled from now on.
+    ///
+    /// Helps line breakpoints and stepping in scripts loaded later.
+    /// Already compiled functions are not affected.
+    /// Evaluated buffers are always compiled with debug info
+    #[clap(visible_alias = "debuginfo")]
+    DebugInfo {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Treat invalid breakpoint specifications as errors.
+    ///
+    /// Applying of config file is aborted on such error
+    StrictSpecs {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Abort `run` batch on command that can't be parsed
+    StopOnError {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Set editor of script buffers, e.g. `vim` or `nano`.
+    ///
+    /// Without program, `$VISUAL` or `$EDITOR` is used, then `hx`.
+    /// If editor fails to launch, system default one is tried
+    Editor {
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        program: Vec<String>,
+    },
+
+    /// Add directory with source files, same as `src add` without prefix
+    SourceDir {
+        /// Path to source root directory
+        path: String,
+    },
+
+    /// Set default time to wait for vm response, in seconds.
+    ///
+    /// Commands with `--timeout` option override it for a single call
+    Timeout {
+        secs: u64,
+    },
+
+    /// Set output format of `backtrace`, `locals`, `examine` and `print`.
+    ///
+    /// In `json` mode each result is printed as single line of JSON, errors stay plain text
+    OutputFormat {
+        #[arg(value_enum)]
+        mode: OutputFormat,
+    },
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -80,36 +228,70 @@ enum SrcCommands {
     },
 }
 
-#[derive(Subcommand, Debug, Clone, Copy)]
+#[derive(Subcommand, Debug, Clone)]
 enum BufferCommands {
     /// Create new empty buffer
     #[clap(visible_alias = "n")]
     New,
 
-    /// Delete buffer by number
+    /// Delete buffer by number or name
     #[clap(visible_alias = "d", visible_alias = "del")]
     Delete {
-        /// Number of buffer
-        num: u32
+        /// Number or name of buffer
+        buf: String
     },
 
-    /// Edit existing buffer by number
+    /// Edit existing buffer by number or name
     #[clap(visible_alias = "e")]
     Edit {
-        /// Number of buffer
-        num: u32
+        /// Number or name of buffer
+        buf: String
     },
 
-    /// Print buffer by number 
+    /// Print buffer by number or name
     #[clap(visible_alias = "p")]
     Print {
-        /// Number of buffer
-        num: u32
+        /// Number or name of buffer
+        buf: String
     },
 
     /// List available buffers
     #[clap(visible_alias = "ls")]
     List,
+
+    /// Give buffer a name, that can be used instead of number
+    Name {
+        /// Number or name of buffer
+        buf: String,
+
+        /// New name. Must be unique and not a number
+        name: String,
+    },
+
+    /// Change buffer number. If new number is taken, buffers are swapped
+    #[clap(visible_alias = "mv")]
+    Move {
+        /// Number or name of buffer
+        buf: String,
+
+        /// New number of buffer
+        num: u32,
+    },
+
+    /// Write buffer to file, e.g. `buf save 2 scripts/dump.nut`
+    Save {
+        /// Number or name of buffer
+        buf: String,
+
+        /// Path to file. Existing file is overwritten
+        path: String,
+    },
+
+    /// Read file into new buffer
+    Load {
+        /// Path to file
+        path: String,
+    },
 }
 
 /// Evaluate specified command every time when other commands evaluated
@@ -136,17 +318,91 @@ enum DisplayCommands {
 /// CLI Frontend commands
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
-    /// Step one debug callback call
+    /// Step one debug callback call, or `count` calls, e.g. `s 10`.
+    ///
+    /// Intermediate events of multiple steps are not printed.
+    /// Stepping stops early on breakpoint or if execution does not halt
     #[clap(visible_alias = "s")]
-    Step,
+    Step {
+        /// Number of steps
+        #[clap(conflicts_with = "while_not")]
+        count: Option<usize>,
+
+        /// Keep stepping until expression, evaluated in current frame, becomes true
+        #[clap(long)]
+        while_not: Option<String>,
+
+        /// Maximum number of steps for `--while-not`
+        #[clap(long, default_value_t = STEP_WHILE_MAX)]
+        max: usize,
+    },
+
+    /// Step single vm instruction.
+    ///
+    /// Squirrel 2 debug hook is called only on line, call and return events,
+    /// there is no per-instruction callback, so this is the same as `step` without count
+    #[clap(visible_alias = "stepi", visible_alias = "si")]
+    StepInstruction,
+
+    /// Step over: continue until next line of current function, 
+    /// without halting in called functions
+    #[clap(visible_alias = "n", visible_alias = "step-over")]
+    Next,
+
+    /// Step out: continue until current function returns and halt in caller.
+    ///
+    /// Returned value is not shown, as it is not available to debug hook
+    #[clap(visible_alias = "fin", visible_alias = "step-out")]
+    Finish,
+
+    /// Step and examine variable on every Enter press.
+    ///
+    /// Non-empty input exits this mode and is executed as usual command
+    Istep {
+        /// Path to variable. See `help examine`
+        path: String,
+    },
 
     /// Continue execution
     #[clap(visible_alias = "c")]
     Continue,
 
+    /// Continue execution until location is reached, e.g. `until main.nut:120`.
+    ///
+    /// Uses one-shot breakpoint, which is removed on any next halt
+    #[clap(visible_alias = "u")]
+    Until {
+        /// Location specification, see `help break`
+        spec: String,
+    },
+
     /// Print call backtrace
     #[clap(visible_alias = "bt")]
-    Backtrace,
+    Backtrace {
+        /// Seconds to wait for response instead of default timeout
+        #[clap(long)]
+        timeout: Option<u64>,
+
+        /// Also copy output to clipboard
+        #[clap(long)]
+        clip: bool,
+    },
+
+    /// Select call stack level, used by `locals` and `examine` without explicit level.
+    ///
+    /// Selection is reset to innermost frame on every new stop.
+    /// Without level selected frame is printed
+    #[clap(visible_alias = "f")]
+    Frame {
+        /// Level of call stack, as shown by backtrace
+        num: Option<usize>,
+    },
+
+    /// Select caller of selected frame
+    Up,
+
+    /// Select callee of selected frame
+    Down,
 
     /// Print local variables list at specified call stack level
     #[clap(visible_alias = "loc")]
@@ -154,22 +410,82 @@ enum Commands {
         /// Level of call stack. Can be found using backtrace.
         /// If not specified, print all
         level: Option<usize>,
+
+        /// Seconds to wait for response instead of default timeout
+        #[clap(long)]
+        timeout: Option<u64>,
+
+        /// Also copy output to clipboard
+        #[clap(long)]
+        clip: bool,
     },
 
-    /// Print value of local variable
+    /// Define alias for sequence of commands separated by `;`, e.g. `alias bpmain b main:1 ; c`.
+    ///
+    /// Arguments after alias are appended to its last command.
+    /// Aliases are not expanded inside of alias. Without expansion alias is removed
+    Alias {
+        /// Name of alias. Can't be name of existing command
+        name: String,
+
+        /// Commands separated by `;`
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        expansion: Vec<String>,
+    },
+
+    /// List defined aliases
+    AliasList,
+
+    /// Define virtual local, computed in current function and listed after real locals.
+    ///
+    /// e.g. `vlocal dist = sqrt(x*x + y*y)`.
+    /// Without expression virtual local is removed, without name all are listed
+    Vlocal {
+        /// Name of virtual local
+        name: Option<String>,
+
+        /// `=` followed by expression
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
+    },
+
+    /// Evaluate expression for each element of array or table in current function.
+    ///
+    /// Element is available as `it`, its key or index as `key`, 
+    /// e.g. `map this.enemies it.hp`. Errors are reported per element
+    Map {
+        /// Dot-separated path to container, see `help examine`
+        target: String,
+
+        /// Expression to evaluate for each element
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
+
+        /// Depth of containers expansion in results
+        #[clap(long, short, default_value_t = 1)]
+        depth: usize,
+    },
+
+    /// Print value of local variable or expression
     #[clap(visible_alias = "x")]
     Examine {
-        /// Dot-separated path to target variable. 
+        /// Dot-separated path to target variable, optionally followed by call stack level. 
         /// 
-        /// e.g. `this.tableX.instanceY.target` or `this.arrayX.42`.
+        /// e.g. `this.tableX.instanceY.target` or `this.arrayX.42 2`.
+        ///
+        /// Negative array indices count from the end: `this.arrayX.-1`.
+        /// Last segment may be array slice: `this.arrayX.2:5` or `this.arrayX.-3:`
         /// 
         /// Also you can prefix path with call stack level like this: `1.this.varX`.
-        target: String,
-
-        /// Specify level of call stack.
+        /// If level is not specified, first found valid path is printed.
         ///
-        /// If not specified, print first found valid path.
-        level: Option<usize>,
+        /// Keys with spaces or dots can be quoted: `this."my key".x` or `this['my.key'].x`
+        ///
+        /// Anything else is evaluated as expression with locals of selected frame,
+        /// e.g. `x this.count + 1`. Evaluation may change state of vm.
+        /// Options other than depth apply only to paths and must precede expression
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        target: Vec<String>,
 
         /// Depth of eager containers (table, array, etc.) expansion.
         /// 
@@ -181,322 +497,2275 @@ enum Commands {
         /// 
         /// - 3.. - and so on
         #[clap(short, long, default_value = "1")]  
-        depth: usize
-    },
+        depth: usize,
 
-    /// Add new breakpoint
-    #[clap(visible_alias = "b", visible_alias = "break")]
-    BreakpointAdd {
-        /// Breakpoint specification.
+        /// Comma-separated list of keys to expand, e.g. `hp,mana,pos`.
         ///
-        /// Must be in format [file:<src>]:[function]:[line].
+        /// Other keys of target container will be shown collapsed.
+        /// Expansion depth is applied to listed keys
+        #[clap(long, value_delimiter = ',')]
+        only: Vec<String>,
+
+        /// Print value as squirrel literal, that can be pasted into script.
         ///
-        /// At least 1 parameter must be specified.
-        spec: String
+        /// Closures, instances and other values without literal form
+        /// are printed as `null` with type comment
+        #[clap(long)]
+        as_source: bool,
+
+        /// Show variables captured by closures as nested containers.
+        ///
+        /// Captured variables are one more level of expansion, 
+        /// so depth is increased by 1
+        #[clap(long)]
+        into_closures: bool,
+
+        /// Rendering of integers, including ones in expanded containers.
+        ///
+        /// `char` prints decimal value followed by character with this code
+        #[clap(short, long, value_enum, default_value = "dec")]
+        format: IntFormat,
+
+        /// Seconds to wait for response instead of default timeout
+        #[clap(long)]
+        timeout: Option<u64>,
+
+        /// Also copy output to clipboard
+        #[clap(long)]
+        clip: bool,
     },
 
-    /// Enable breakpoint. If number not specified, enable all
-    #[clap(visible_alias = "be")]
-    BreakpointEnable {
-        /// Breakpoint number
-        num: Option<u32>
+    /// Print one line summary of current stop: function, location, reason and `this` type
+    Where,
+
+    /// Halt once, when call stack depth reaches `depth`, e.g. to catch runaway recursion.
+    ///
+    /// Without depth pending trigger is shown
+    BreakDepth {
+        /// Call stack depth, as backtrace length. 0 disables pending trigger
+        depth: Option<usize>,
     },
 
-    /// Disable breakpoint. If number not specified, disable all
-    #[clap(visible_alias = "bd")]
-    BreakpointDisable {
-        /// Breakpoint number
-        num: Option<u32>
+    /// Print count of messages not yet handled by vm and whether response is awaited
+    Queue,
+
+    /// Detach from current vm and attach to the newest one, e.g. after scripts reload.
+    ///
+    /// Breakpoints are moved to new vm, buffers, watches and other frontend state are kept
+    Reattach,
+
+    /// Remove debug hook and let vm run freely until new vm is bound.
+    ///
+    /// Prompt stays available, new vm is attached like `reattach` does after the next command
+    Detach,
+
+    /// Print whole source of function at call stack level or by name.
+    ///
+    /// Function range is taken from line info of function, text is read from registered source files,
+    /// see `help src`. If function is not reachable from root table, range is found by scanning sources
+    SourceFn {
+        /// Level of call stack or function path (see `help b`).
+        /// If not specified, current function is used
+        target: Option<String>,
     },
 
-    /// Clear breakpoint. If number not specified, clear all
-    #[clap(visible_alias = "bc")]
-    BreakpointClear {
-        /// Breakpoint number
-        num: Option<u32>
+    /// Print line info table of function at call stack level or by name:
+    /// offsets of instructions and source lines they were compiled from, sorted by offset.
+    ///
+    /// Functions are looked up among closures reachable from root table and functions declared in them
+    #[clap(visible_alias = "lineinfo")]
+    LineInfo {
+        /// Level of call stack or function name. 
+        /// If not specified, current function is used
+        target: Option<String>,
     },
 
-    /// List all breakpoints
-    #[clap(visible_alias = "bl")]
-    BreakpointList,
+    /// Print declared arguments of function at specified call stack level
+    #[clap(visible_alias = "sig")]
+    Signature {
+        /// Level of call stack. If not specified, current function is used
+        level: Option<usize>,
+    },
 
-    /// Compile and run arbitrary squirrel code
+    /// Print details of call stack frame: function, source, line, call site and locals count
+    InfoFrame {
+        /// Level of call stack. If not specified, selected frame is used
+        level: Option<usize>,
+    },
+
+    /// Print internal representation of value: type tag and raw bytes of object handler.
     ///
-    /// Local variables to be captured in compiled closure may be specified
-    /// in list on first script line like this:
+    /// Intended for diagnostics of value marshaling. Read-only
+    Raw {
+        /// Dot-separated path to target variable, see `help examine`
+        target: String,
+
+        /// Specify level of call stack.
+        #[clap(long, short, default_value_t = 1)]
+        level: usize,
+    },
+
+    /// Set value of scalar member of container, e.g. `set-var this.hp 100`.
     ///
-    ///     |3.this, 1.capture_local1, 2.capture_local2, ...|
+    /// Sqvm can't set function locals themselves, only members of tables, arrays, etc.
+    #[clap(visible_alias = "set-var")]
+    SetLocal {
+        /// Dot-separated path to target, see `help examine`
+        target: String,
+
+        /// Integer, float, `true`, `false` or quoted string, which may contain spaces
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        value: Vec<String>,
+    },
+
+    /// Print parameters of function with their default values.
     ///
-    /// where local variable name is prefixed with call stack level.
-    /// Note that `lvl.this` will be renamed to this_lvl, e.g. this_3
-    #[clap(visible_alias = "eval")]
-    Evaluate {
-        /// If specified, enable debugging of compiled script
-        #[clap(visible_alias = "dbg", long)]
-        debug: bool, 
+    /// Parameters without default value are shown as `<required>`
+    Defaults {
+        /// Level of call stack or expression, evaluated in current function, 
+        /// that returns function (e.g. `Foo.bar`). If not specified, current function is used
+        target: Option<String>,
+    },
 
-        /// Choose script buffer to evaluate. If not specified, new buffer will be created.
-        buffer: Option<u32>,
+    /// Evaluate expression in current function and print its value.
+    ///
+    /// Locals of current function are captured, `this` is available as `this_1`.
+    ///
+    /// Block of `;`-separated statements in braces is also accepted,
+    /// its value is a value of the last statement, e.g. `print { local t = f(); t * 2 }`
+    #[clap(visible_alias = "p")]
+    Print {
+        /// Expression or block to evaluate
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
 
         /// Depth of eager returned containers (table, array, etc.) expansion.
         /// Check `help examine` for more info.
         #[clap(short, long, default_value = "1")]
-        depth: usize
+        depth: usize,
     },
 
-    /// Add, remove, edit and view script buffers
-    #[clap(visible_alias = "buf")]
-    #[command(subcommand)]
-    Buffer(BufferCommands),
+    /// Print global variables, i.e. contents of root table
+    Globals {
+        /// Depth of containers expansion, see `help examine`
+        #[clap(short, long, default_value = "1")]
+        depth: usize,
+    },
 
-    /// Continue execution, but print every debug event.
+    /// Search locals of all call stack levels for values equal to specified one.
     ///
-    /// Warning: due to heavy use of stdout, it may be hard to send stop command to debugger,
-    /// use breakpoints instead
-    #[clap(visible_alias = "t")]
-    Trace,
+    /// Prints paths to all matches, e.g. `find 42` or `find "text"`
+    Find {
+        /// Value to search for. Any squirrel literal may be used
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        value: Vec<String>,
 
-    /// Add, remove, or display code source files
-    #[command(subcommand)]
-    Src(SrcCommands),
+        /// Also search in root table
+        #[clap(short, long)]
+        global: bool,
 
-    #[command(subcommand)]
-    Display(DisplayCommands),
+        /// Max depth of nested containers to search in
+        #[clap(short, long, default_value = "3")]
+        depth: usize,
+    },
 
-    /// Set values of different debugging variables
-    #[command(subcommand)]
-    Set(SetCommands),
+    /// Evaluate expression in current function and compare it with expected value.
+    ///
+    /// Locals of current function are captured, `this` is available as `this_1`.
+    ///
+    /// e.g. `expect hp * 2 == 100` or `expect this_1.pos == [1, 2]`
+    Expect {
+        /// Expression and expected value, separated with `==`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
 
-    /// Save breakpoints and buffers.
-    Save {
-        /// File to save state.
-        /// If not specified, default file will be used
-        file: Option<String>,
+        /// Depth of compared containers expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "4")]
+        depth: usize,
     },
-    
-    /// Load breakpoints and buffers.
-    Load {
-        /// File to load state from.
-        /// If not specified, default file will be used
-        file: Option<String>,
+
+    /// Print time passed and count of debug events received since debugger attach.
+    ///
+    /// Line events count roughly corresponds to count of executed script lines
+    Uptime {
+        /// Restart counting
+        #[clap(long)]
+        reset: bool,
     },
 
-    /// Exit process
-    Exit,
-}
+    /// Measure debug hook overhead.
+    ///
+    /// Execution is continued for specified time and halted after that
+    Overhead {
+        /// Seconds to run vm for measurement
+        #[clap(default_value = "1")]
+        secs: u64,
+    },
 
-#[derive(Clone, Serialize, Deserialize)]
-struct SavedState {
-    buffers: ScriptBuffers,
-    breakpoints: dbg::BreakpointStore,
-    src_dirs: Vec<(String, Option<String>)>,
-    display_cmd: Vec<String>,
-}
+    /// Count calls of each function while execution continues.
+    ///
+    /// Counts are kept when disabled, see `profile-report` and `profile-reset`
+    Profile {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
 
-/// CLI Frontend for SQ debugger
-pub struct DebuggerFrontend {
-    last_cmd: Option<Commands>,
-    buffers: ScriptBuffers,
-    during_eval: bool,
-    srcs: SourceDB,
-    display_cmds: SavedCommands,
-    /// Combined Call/Ret and Line events
-    last_event: Arc<RwLock<BrkSpec>>,
-}
+    /// Print counts of function calls, most called first
+    ProfileReport {
+        /// Count of functions to print. If not specified, print all
+        count: Option<usize>,
+    },
 
-/// Private methods
-impl DebuggerFrontend {
-    fn print_backtrace(bt: dbg::SqBacktrace) {
-        println!("Backtrace:");
-        for (lvl, info) in bt.into_iter().enumerate() {
-            println!("{:03}: {info}", lvl + 1);
-        }
-    }
+    /// Measure inclusive time spent in each function while execution continues.
+    ///
+    /// Time when vm is halted or handled by debugger is not counted.
+    /// Functions entered before start are not measured
+    ProfileTime {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
 
-    /// Get CLI parser
+    /// Print time spent in functions, slowest first
+    ProfileTimeReport {
+        /// Count of functions to print. If not specified, print all
+        count: Option<usize>,
+    },
+
+    /// Write call stacks measured by `profile-time` to file in folded format,
+    /// consumable by flamegraph tools, e.g. `inferno-flamegraph < out.folded > out.svg`.
+    ///
+    /// Stacks are weighted by microseconds spent in their last function
+    ProfileFlame {
+        /// Path to file. Existing file is overwritten
+        path: String,
+    },
+
+    /// Record lines reached while execution continues.
+    ///
+    /// Covered lines are kept when disabled, see `coverage-report` and `profile-reset`
+    Coverage {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Write lines recorded by `coverage` to file.
+    ///
+    /// Report is json object of source -> covered lines if path ends with `.json`, lcov tracefile otherwise
+    CoverageReport {
+        /// Path to file. Existing file is overwritten
+        path: String,
+    },
+
+    /// Clear counts of function calls, measured times, stacks and covered lines
+    ProfileReset,
+
+    /// Halt when error is not caught by script.
+    ///
+    /// Vm is halted on the next event after throw, error is printed with backtrace of throw site
+    #[clap(visible_alias = "catch")]
+    CatchErrors {
+        #[arg(value_enum)]
+        active: BoolVal,
+    },
+
+    /// Print last errors thrown by vm with their backtraces
+    #[clap(visible_alias = "err")]
+    Errors {
+        /// Count of last errors to print. If not specified, print all recorded
+        count: Option<usize>,
+
+        /// Clear recorded errors
+        #[clap(long)]
+        clear: bool,
+    },
+
+    /// Add new breakpoint
+    #[clap(visible_alias = "b", visible_alias = "break")]
+    BreakpointAdd {
+        /// Breakpoint specification.
+        ///
+        /// Must be in format [file:<src>]:[function]:[line].
+        ///
+        /// At least 1 parameter must be specified.
+        spec: String,
+
+        /// Optional `ignore <n>`, condition `if <expr>` and message `log <template>`, 
+        /// e.g. `b foo:42 ignore 5 if this_1.count > 10`.
+        ///
+        /// First `n` hits do not halt execution.
+        /// Expression is evaluated in halted function with its locals,
+        /// `this` is available as `this_1`. Failed condition halts execution.
+        ///
+        /// Breakpoint with message is a logpoint: it does not halt, but prints message
+        /// with `{expr}` placeholders evaluated like condition,
+        /// e.g. `b file:foo.nut:foo:10 log "count={this_1.count}"`.
+        /// Message after condition must be quoted
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        condition: Vec<String>,
+
+        /// On each hit save locals of current function to snapshot
+        /// named after breakpoint number and debugger uptime, e.g. `bp3@12.345s`.
+        /// Combined with logpoint, locals are saved without halting
+        #[clap(long)]
+        snapshot: bool,
+    },
+
+    /// Add temporary breakpoint, that is removed after first halt on it.
+    ///
+    /// Arguments are the same as of `break`
+    #[clap(visible_alias = "tbreak", visible_alias = "tb")]
+    BreakpointTemp {
+        /// Breakpoint specification, see `help break`
+        spec: String,
+
+        /// Optional `ignore <n>` and condition `if <expr>`, see `help break`
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        condition: Vec<String>,
+    },
+
+    /// Add breakpoint at current source line
+    #[clap(visible_alias = "bh")]
+    BreakHere,
+
+    /// Add expression that will be evaluated in current function on every halt.
+    ///
+    /// e.g. `watch this_1.hp * 2`. Expressions failed to evaluate are shown as `<unavailable>`
+    #[clap(visible_alias = "w")]
+    Watch {
+        /// Expression to evaluate. See `help print`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
+    },
+
+    /// Add expression that will be evaluated only when halted at matching location.
+    ///
+    /// e.g. `watch-at file:main.nut:Update hp * 2`
+    WatchAt {
+        /// Location specification. See `help b`
+        spec: String,
+
+        /// Expression to evaluate. See `help print`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expr: Vec<String>,
+    },
+
+    /// Halt when value of local or its child changes, e.g. `watch-var this.hp`.
+    ///
+    /// Path is resolved in selected frame, see `help examine`.
+    /// Watchpoint is removed when function of local returns.
+    /// Without target watchpoints are listed
+    #[clap(visible_alias = "wv")]
+    WatchVar {
+        /// Dot-separated path to local variable or its child
+        target: Option<String>,
+    },
+
+    /// Delete data watchpoint. If number not specified, delete all
+    UnwatchVar {
+        /// Watchpoint number
+        num: Option<u32>,
+    },
+
+    /// Delete watch expression. If number not specified, delete all
+    #[clap(visible_alias = "watch-delete")]
+    Unwatch {
+        /// Watch number
+        num: Option<u32>,
+    },
+
+    /// List all watch expressions
+    #[clap(visible_alias = "wl")]
+    WatchList,
+
+    /// Add breakpoints at every line, that calls function by name.
+    ///
+    /// Bytecode of closures reachable from root table and functions declared in them is scanned.
+    /// Calls by slot name match methods of any class with the same name, e.g. `obj.func()`.
+    /// Calls through locals or expressions can not be found statically, only their count is reported
+    BreakCallers {
+        /// Name of called function
+        func: String,
+    },
+
+    /// Enable breakpoint. If number not specified, enable all
+    #[clap(visible_alias = "be")]
+    BreakpointEnable {
+        /// Breakpoint number
+        num: Option<u32>
+    },
+
+    /// Disable breakpoint. If number not specified, disable all
+    #[clap(visible_alias = "bd")]
+    BreakpointDisable {
+        /// Breakpoint number
+        num: Option<u32>
+    },
+
+    /// Set count of first hits of breakpoint that do not halt execution
+    #[clap(visible_alias = "bi")]
+    BreakpointIgnore {
+        /// Breakpoint number
+        num: u32,
+
+        /// Count of ignored hits. Hits made before are counted too
+        count: u32,
+    },
+
+    /// Clear breakpoint. If number not specified, clear all
+    #[clap(visible_alias = "bc")]
+    BreakpointClear {
+        /// Breakpoint number
+        num: Option<u32>
+    },
+
+    /// List all breakpoints with count of matches, including ignored ones
+    #[clap(visible_alias = "bl")]
+    BreakpointList,
+
+    /// List breakpoints hit since last `continue` or `until`, most hits first.
+    /// Ignored hits are not counted, see `bl` for them
+    Hits {
+        /// Reset hit counts
+        #[clap(long)]
+        reset: bool,
+    },
+
+    /// Disable breakpoint with most hits since last `continue`, as listed by `hits`
+    DisableNoisiest,
+
+    /// Check breakpoints against registered source files
+    /// and report ones that can't be reached: unknown file, function or line out of range
+    BreakVerify,
+
+    /// Compile and run arbitrary squirrel code
+    ///
+    /// Local variables to be captured in compiled closure may be specified
+    /// in list on first script line like this:
+    ///
+    ///     |3.this, 1.capture_local1, 2.capture_local2, ...|
+    ///
+    /// where local variable name is prefixed with call stack level.
+    /// Note that `lvl.this` will be renamed to this_lvl, e.g. this_3
+    #[clap(visible_alias = "eval")]
+    Evaluate {
+        /// If specified, enable debugging of compiled script
+        #[clap(visible_alias = "dbg", long)]
+        debug: bool, 
+
+        /// Number or name of script buffer to evaluate. 
+        /// If not specified, new buffer will be created.
+        buffer: Option<String>,
+
+        /// Depth of eager returned containers (table, array, etc.) expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "1")]
+        depth: usize,
+
+        /// Pass locals of named snapshot to script instead of live values.
+        ///
+        /// Values are copies: containers lose identity, while instances, 
+        /// closures and other values that can't be copied are passed as tables or nulls
+        #[clap(long, conflicts_with = "debug")]
+        snapshot: Option<String>,
+
+        /// Collect output printed by script and show it after result
+        #[clap(long, conflicts_with = "debug")]
+        capture_output: bool,
+    },
+
+    /// Compile and run squirrel script file without adding it to buffers.
+    /// Captured locals may be listed on first line, check `help evaluate`
+    Source {
+        /// Path to script file
+        path: String,
+
+        /// If specified, enable debugging of compiled script
+        #[clap(visible_alias = "dbg", long)]
+        debug: bool,
+
+        /// Depth of eager returned containers (table, array, etc.) expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "1")]
+        depth: usize,
+    },
+
+    /// Save locals of current function or single variable to named snapshot. 
+    /// If name not specified, list snapshots
+    Snapshot {
+        /// Name of snapshot. Existing snapshot with same name is overwritten
+        name: Option<String>,
+
+        /// Path to variable to save instead of all locals, see `help examine`
+        target: Option<String>,
+
+        /// Depth of eager containers (table, array, etc.) expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "4")]
+        depth: usize,
+    },
+
+    /// Compare snapshot with current value and print added, removed and changed keys.
+    ///
+    /// Target must be the same as one snapshot was taken of, without it all locals are compared
+    Diff {
+        /// Name of snapshot
+        name: String,
+
+        /// Path to variable, see `help examine`
+        target: Option<String>,
+
+        /// Depth of eager containers (table, array, etc.) expansion.
+        /// Should match depth of snapshot
+        #[clap(short, long, default_value = "4")]
+        depth: usize,
+    },
+
+    /// Add, remove, edit and view script buffers
+    #[clap(visible_alias = "buf")]
+    #[command(subcommand)]
+    Buffer(BufferCommands),
+
+    /// Continue execution, but print every debug event, or only ones matching filters.
+    ///
+    /// Warning: due to heavy use of stdout, it may be hard to send stop command to debugger,
+    /// use breakpoints or filters instead
+    #[clap(visible_alias = "t")]
+    Trace {
+        /// Print only events of source files, which path contains this string
+        #[clap(long)]
+        src: Option<String>,
+
+        /// Print only events of listed kinds, e.g. `--only calls,returns`.
+        /// Recorded trace gets only printed events too
+        #[clap(long, value_enum, value_delimiter = ',')]
+        only: Vec<TraceKind>,
+
+        /// Also record function calls and returns to file.
+        /// File is written when execution is halted
+        #[clap(long)]
+        out: Option<String>,
+
+        /// Format of recorded trace
+        #[clap(long, value_enum, default_value = "chrome")]
+        format: TraceFormat,
+
+        /// Print arguments of called functions. Slows down tracing
+        #[clap(long)]
+        args: bool,
+
+        /// Collapse identical consecutive events into one line with repeat count
+        #[clap(long)]
+        dedup: bool,
+    },
+
+    /// Print received debug events between two indices of event log.
+    ///
+    /// Every event printed during tracing or stepping is logged with its index.
+    /// Range is half-open, negative indices are relative to the end of log,
+    /// e.g. `log-slice -20` prints the last 20 events
+    #[clap(allow_negative_numbers = true)]
+    LogSlice {
+        /// First event index
+        start: isize,
+
+        /// Index after the last event. If not specified, print till the end of log
+        end: Option<isize>,
+
+        /// Print only events in source files, which path contains this string
+        #[clap(long)]
+        file: Option<String>,
+
+        /// Print only calls and returns of function with this name
+        #[clap(long)]
+        func: Option<String>,
+    },
+
+    /// Add, remove, or display code source files
+    #[command(subcommand)]
+    Src(SrcCommands),
+
+    /// Print source lines around current line, same as `src show`.
+    ///
+    /// Sources are searched in directories added by `set source-dir` or `src add`
+    #[clap(visible_alias = "l")]
+    List {
+        /// Count of lines printed before and after current one
+        #[clap(default_value_t = 5)]
+        radius: usize,
+    },
+
+    #[command(subcommand)]
+    Display(DisplayCommands),
+
+    /// Set values of different debugging variables
+    #[command(subcommand)]
+    Set(SetCommands),
+
+    /// Save values of local variables at call stack level to file.
+    ///
+    /// Values are saved as JSON, containers are expanded up to `depth`.
+    /// Scalars in containers can be written back with `stack-restore`
+    StackSave {
+        /// Level of call stack
+        level: usize,
+
+        /// File to save values
+        file: String,
+
+        /// Depth of eager containers (table, array, etc.) expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "8")]
+        depth: usize,
+    },
+
+    /// UNSAFE: write values saved by `stack-save` back to locals at call stack level.
+    ///
+    /// Vm state is changed behind the back of running scripts and may break their invariants.
+    /// Only scalar members of containers are written, as vm api can't set locals themselves.
+    /// Locals that are scalars, members that are not scalars now and missing ones are skipped.
+    /// Asks for confirmation before writing
+    StackRestore {
+        /// Level of call stack
+        level: usize,
+
+        /// File saved by `stack-save`
+        file: String,
+
+        /// Do not ask for confirmation, e.g. in batch files
+        #[clap(long)]
+        yes: bool,
+    },
+
+    /// Save value of variable to file as JSON, containers are expanded up to `depth`.
+    ///
+    /// Global variables are prefixed with `::`, e.g. `::gameState.players`, `::` is whole root table
+    Dump {
+        /// Path to local or global variable, see `help examine`
+        target: String,
+
+        /// File to save value. Existing file is overwritten
+        path: String,
+
+        /// Depth of eager containers (table, array, etc.) expansion.
+        /// Check `help examine` for more info.
+        #[clap(short, long, default_value = "8")]
+        depth: usize,
+    },
+
+    /// Execute debugger commands from file, one per line, e.g. to set breakpoints and continue.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    /// Invalid command is reported and skipped, unless `set stop-on-error on`.
+    /// Command failed due to `set strict-specs on` always aborts batch
+    Run {
+        /// Path to file with commands
+        path: String,
+    },
+
+    /// Save breakpoints and buffers.
+    ///
+    /// Format is chosen by file extension: `.toml`, `.yaml`/`.yml` or JSON otherwise
+    Save {
+        /// File to save state.
+        /// If not specified, default file will be used
+        file: Option<String>,
+    },
+    
+    /// Load breakpoints and buffers.
+    ///
+    /// Format is chosen by file extension like in `save`
+    Load {
+        /// File to load state from.
+        /// If not specified, default file will be used
+        file: Option<String>,
+    },
+
+    /// Exit process
+    Exit,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedState {
+    buffers: ScriptBuffers,
+    breakpoints: dbg::BreakpointStore,
+    src_dirs: Vec<SavedSrcDir>,
+    display_cmd: Vec<String>,
+    /// Virtual locals: `(name, expression)`
+    #[serde(default)]
+    vlocals: Vec<(String, String)>,
+    /// Command aliases: `(name, expansion)`
+    #[serde(default)]
+    aliases: Vec<(String, String)>,
+}
+
+/// Source directory with optional prefix.
+/// Also deserializes from `[path, prefix]` array written by older versions
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedSrcDir {
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+}
+
+/// Format of state file
+#[derive(Clone, Copy)]
+enum StateFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl StateFormat {
+    /// Infer format from file extension, JSON is used for unknown extensions
+    fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// Local variable saved with `stack-save`
+#[derive(Serialize)]
+struct SavedLocal {
+    name: String,
+    value: DynSqVar,
+}
+
+#[derive(Serialize)]
+struct SavedFrame {
+    level: usize,
+    locals: Vec<SavedLocal>,
+}
+
+/// Frame saved with `stack-save`, read back by `stack-restore`.
+/// Values are kept as JSON, their keys lost types on save
+#[derive(Deserialize)]
+struct LoadedFrame {
+    locals: Vec<LoadedLocal>,
+}
+
+#[derive(Deserialize)]
+struct LoadedLocal {
+    name: String,
+    value: serde_json::Value,
+}
+
+/// Locals captured by hook on hit of breakpoint with snapshot option
+struct HitSnapshot {
+    number: u32,
+    received: Instant,
+    locals: Vec<SqLocalVar>,
+}
+
+/// CLI Frontend for SQ debugger
+pub struct DebuggerFrontend {
+    last_cmd: Option<Commands>,
+    buffers: ScriptBuffers,
+    during_eval: bool,
+    /// Hooks are removed from current vm, next bound vm is attached when available
+    detached: bool,
+    srcs: SourceDB,
+    display_cmds: SavedCommands,
+    /// Combined Call/Ret and Line events
+    last_event: Arc<RwLock<BrkSpec>>,
+    max_buffers: usize,
+    watches: WatchStore,
+    /// Active trace recording, shared with events printing loop
+    trace_rec: Arc<Mutex<Option<TraceRecorder>>>,
+    /// Last received debug events, shared with events printing loop
+    event_log: Arc<Mutex<EventLog>>,
+    /// Why execution was halted last time, shared with events printing loop
+    stop_reason: Arc<RwLock<StopReason>>,
+    /// Named snapshots of values
+    snapshots: IndexMap<String, DynSqVar>,
+    /// Virtual locals: `(name, expression)`
+    vlocals: Vec<(String, String)>,
+    /// Command aliases: `(name, expansion)`
+    aliases: Vec<(String, String)>,
+    /// Snapshots captured on breakpoint hits, not yet saved. Shared with events printing loop
+    hit_snapshots: Arc<Mutex<Vec<HitSnapshot>>>,
+    /// Receiver of debug events of current vm, shared with events printing loop
+    event_rx: Arc<RwLock<Receiver<dbg::DebugEventBundle>>>,
+    /// Invalid breakpoint specifications are errors
+    strict_specs: bool,
+    /// Last command failed in strict mode. Reset by batch runner before and after each command
+    cmd_failed: bool,
+    /// Abort batch on command that can't be parsed
+    stop_on_error: bool,
+    /// Canonical paths of batch files being executed, outermost first
+    running_batches: Vec<std::path::PathBuf>,
+    /// Collapse repeated trace events, shared with events printing loop
+    trace_dedup: Arc<atomic::AtomicBool>,
+    /// Next step event is not printed, shared with events printing loop
+    quiet_step: Arc<atomic::AtomicBool>,
+    /// Count of handled events vm halted at, shared with events printing loop
+    halt_events: Arc<atomic::AtomicUsize>,
+    /// Depth trigger set, but not reported yet
+    armed_depth: Option<usize>,
+    /// Editor of buffers with its arguments, overrides environment
+    editor: Option<Vec<String>>,
+    /// Number of one-shot breakpoint set by `until`
+    until_bp: Option<u32>,
+    /// Selected call stack level and event log position, at which it was selected
+    frame: Option<(usize, usize)>,
+    /// Format of backtrace, locals and examined values
+    output_format: OutputFormat,
+}
+
+/// Private methods
+impl DebuggerFrontend {
+    fn print_backtrace(bt: &dbg::SqBacktrace) {
+        println!("Backtrace:");
+        Self::print_frames(bt);
+    }
+
+    /// Print value as single line of JSON
+    fn print_json(value: &impl Serialize) {
+        match serde_json::to_string(value) {
+            Ok(json) => println!("{json}"),
+            Err(e) => println!("failed to serialize: {e}"),
+        }
+    }
+
+    /// Print call stack frames along with their call sites
+    fn print_frames(bt: &dbg::SqBacktrace) {
+        print!("{}", Self::format_frames(bt));
+    }
+
+    /// Format call stack frames, one per line
+    fn format_frames(bt: &dbg::SqBacktrace) -> String {
+        let mut out = String::new();
+        for (lvl, info) in bt.iter().enumerate() {
+            // Current line of caller frame is the call site
+            match bt.get(lvl + 1) {
+                Some(SqStackInfo { src_file, line, .. }) => writeln!(out,
+                    "{:03}: {info}, called from {src}:{ln}", 
+                    lvl + 1,
+                    src = src_file.as_deref().unwrap_or("??"),
+                    ln = line.map_or("??".into(), |l| l.to_string()),
+                ),
+                None => writeln!(out, "{:03}: {info}", lvl + 1),
+            }.unwrap();
+        }
+        out
+    }
+
+    /// Copy text to system clipboard
+    fn copy_to_clipboard(text: String) {
+        match arboard::Clipboard::new().and_then(|mut c| c.set_text(text)) {
+            Ok(()) => println!("copied to clipboard"),
+            Err(e) => println!("failed to copy to clipboard: {e}"),
+        }
+    }
+
+    /// Get locals of current function to be captured by evaluated script
+    fn frame_capture(dbg: &dbg::SqDebugger) -> Vec<dbg::SqCaptureLocal> {
+        Self::frame_capture_at(dbg, 1)
+    }
+
+    /// Get locals of function at call stack level to be captured by evaluated script
+    fn frame_capture_at(dbg: &dbg::SqDebugger, level: usize) -> Vec<dbg::SqCaptureLocal> {
+        match dbg.get_locals(Some(level), 0) {
+            Ok(locs) => locs.into_iter()
+                .map(|SqLocalVarWithLvl { var, lvl }| (var.name, lvl))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Split script on top-level `;`, ignoring ones in strings and brackets
+    fn split_statements(src: &str) -> Vec<&str> {
+        let mut out = vec![];
+        let mut nesting = 0usize;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut start = 0;
+
+        for (idx, c) in src.char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '(' | '[' | '{') => nesting += 1,
+                (None, ')' | ']' | '}') => nesting = nesting.saturating_sub(1),
+                (None, ';') if nesting == 0 => {
+                    out.push(src[start..idx].trim());
+                    start = idx + 1;
+                }
+                _ => (),
+            }
+        }
+        out.push(src[start..].trim());
+        out.retain(|s| !s.is_empty());
+        out
+    }
+
+    /// Split expectation on the last top-level `==`, ignoring ones in strings and brackets
+    fn split_expectation(expr: &str) -> Option<(&str, &str)> {
+        let mut nesting = 0usize;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut split = None;
+
+        for (idx, c) in expr.char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '(' | '[' | '{') => nesting += 1,
+                (None, ')' | ']' | '}') => nesting = nesting.saturating_sub(1),
+                (None, '=') if nesting == 0 && expr[idx + 1..].starts_with('=') => split = Some(idx),
+                _ => (),
+            }
+        }
+        split.map(|idx| (&expr[..idx], &expr[idx + 2..]))
+    }
+
+    /// Wrap expression or `{ ... }` block into script that returns its value.
+    ///
+    /// Value of block is a value of its last statement
+    fn wrap_expression(src: &str) -> String {
+        // Statements that have no value
+        const NO_VALUE: &[&str] = &[
+            "local", "return", "if", "for", "foreach", "while", "do", "switch",
+            "function", "class", "throw", "try", "const", "enum",
+        ];
+
+        let src = src.trim();
+        let block = src.strip_prefix('{').and_then(|b| b.strip_suffix('}'));
+        let mut stmts = block.map(Self::split_statements).unwrap_or_default();
+
+        // Block without `;` is a table literal
+        if !matches!(block, Some(b) if stmts.len() > 1 || b.trim_end().ends_with(';')) {
+            return format!("return ({src});");
+        }
+
+        let Some(last) = stmts.pop() else {
+            return "return null;".into();
+        };
+
+        let keyword = last.split(|c: char| !c.is_alphanumeric() && c != '_').next();
+        let last = if matches!(keyword, Some(k) if NO_VALUE.contains(&k)) {
+            format!("{last};")
+        } else {
+            format!("return ({last});")
+        };
+
+        stmts.iter().map(|s| format!("{s}; ")).collect::<String>() + &last
+    }
+
+    /// Evaluate expression or block with locals of call stack level and print result.
+    /// Returns printed value
+    fn print_expression(
+        &self, 
+        dbg: &dbg::SqDebugger, 
+        expr: &str, 
+        level: usize, 
+        depth: usize, 
+        ints: SqIntFormat,
+    ) -> Option<String> {
+        if self.during_eval {
+            println!("failed to evaluate: cannot evaluate during evaluation");
+            return None;
+        }
+
+        match dbg.execute(Self::wrap_expression(expr), Self::frame_capture_at(dbg, level), depth) {
+            Ok(res) if self.output_format == OutputFormat::Json => {
+                let text = Self::json_value(expr, &res);
+                println!("{text}");
+                Some(text)
+            }
+            Ok(res) => {
+                let text = res.formatted(false, ints).to_string();
+                println!("{text}");
+                Self::warn_overflow(&res, "result");
+                Some(text)
+            }
+            Err(e @ error::SqDebugError::CompileError(_)) => {
+                println!("failed to compile: {e}");
+                println!("  in: {expr}");
+                None
+            }
+            Err(e) => {
+                println!("failed to evaluate: {e}");
+                None
+            }
+        }
+    }
+
+    /// Format named value as JSON object with its name, type and value
+    fn json_value(name: &str, val: &DynSqVar) -> String {
+        serde_json::json!({
+            "name": name,
+            "type": format!("{:?}", val.get_type()),
+            "value": val,
+        }).to_string()
+    }
+
+    /// Check if examine target is a path to local, not an expression
+    fn is_local_path(target: &str) -> bool {
+        use SqPathToken::*;
+        let mut prev = None;
+        // Index from the end or slice not after dot is part of expression, e.g. `x-1`
+        !target.is_empty() && SqPathToken::lexer(target).all(|t| {
+            let valid = match t {
+                Error => false,
+                NegIndex(_) | Slice(_) => matches!(prev, Some(Dot)),
+                _ => true,
+            };
+            prev = Some(t);
+            valid
+        })
+    }
+
+    /// Replace `this` identifiers outside of strings with captured `this_<level>`
+    fn rename_this(expr: &str, level: usize) -> String {
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        let mut out = String::with_capacity(expr.len());
+        let mut quote = None;
+        let mut escaped = false;
+        let mut rest = expr;
+
+        while let Some(c) = rest.chars().next() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, _) if rest.starts_with("this")
+                    && !rest[4..].starts_with(is_ident)
+                    && !out.ends_with(|p: char| is_ident(p) || p == '.') => {
+                    write!(out, "this_{level}").unwrap();
+                    rest = &rest[4..];
+                    continue;
+                }
+                _ => (),
+            }
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        out
+    }
+
+    /// If enabled, print paths of integers near `SQInteger` bounds
+    fn warn_overflow(val: &DynSqVar, root: &str) {
+        fn collect(val: &DynSqVar, path: String, found: &mut Vec<(String, isize)>) {
+            match val {
+                DynSqVar::Integer(i) if *i >= isize::MAX - OVERFLOW_MARGIN 
+                    || *i <= isize::MIN + OVERFLOW_MARGIN => found.push((path, *i)),
+                DynSqVar::Table(map)
+                | DynSqVar::Class(map)
+                | DynSqVar::Instance(SqInstance { this: map }) => for (key, child) in map {
+                    let key = match key {
+                        DynSqVar::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    collect(child, format!("{path}.{key}"), found);
+                }
+                DynSqVar::Array(v) => for (idx, child) in v.iter().enumerate() {
+                    collect(child, format!("{path}.{idx}"), found);
+                }
+                _ => (),
+            }
+        }
+
+        if !OVERFLOW_WARN.load(atomic::Ordering::Relaxed) {
+            return;
+        }
+
+        let mut found = vec![];
+        collect(val, root.to_string(), &mut found);
+        for (path, i) in found {
+            let bound = if i > 0 { "max" } else { "min" };
+            println!("warning: {path} = {i} is near {bound} integer, possible overflow");
+        }
+    }
+
+    /// Recursively search `val` for values equal to `needle` and collect paths to them.
+    ///
+    /// `budget` limits count of visited values
+    fn find_value(
+        val: &DynSqVar,
+        needle: &DynSqVar,
+        path: String,
+        found: &mut Vec<String>,
+        budget: &mut usize
+    ) {
+        if *budget == 0 {
+            return;
+        }
+        *budget -= 1;
+
+        if val.deep_eq(needle) {
+            found.push(path.clone());
+        }
+
+        match val {
+            DynSqVar::Table(map)
+            | DynSqVar::Class(map)
+            | DynSqVar::Instance(SqInstance { this: map }) => for (key, child) in map {
+                Self::find_value(child, needle, child_path(&path, key), found, budget);
+            }
+            DynSqVar::Array(v) => for (idx, child) in v.iter().enumerate() {
+                Self::find_value(child, needle, child_path(&path, &DynSqVar::Integer(idx as isize)), found, budget);
+            }
+            _ => (),
+        }
+    }
+
+    /// Print root table in the same form as `examine`
+    fn print_globals(&self, dbg: &dbg::SqDebugger, depth: usize) {
+        let root = match dbg.get_root_table(depth) {
+            Ok(root) => root,
+            Err(e) => return println!("failed to get root table: {e}"),
+        };
+
+        if self.output_format == OutputFormat::Json {
+            println!("{}", Self::json_value("roottable", &root));
+        } else {
+            println!("roottable: {typ:?} = {root}", typ = root.get_type());
+            Self::warn_overflow(&root, "roottable");
+        }
+    }
+
+    /// Search locals and optionally globals for value
+    fn find(&self, dbg: &dbg::SqDebugger, value: &str, global: bool, depth: usize) {
+        if self.during_eval {
+            println!("failed to evaluate: cannot evaluate during evaluation");
+            return;
+        }
+
+        // Vm is used to parse literal
+        let needle = match dbg.execute(format!("return ({value});"), vec![], 0) {
+            Ok(needle) => needle,
+            Err(e) => return println!("failed to evaluate value: {e}"),
+        };
+
+        let mut found = vec![];
+        let mut budget = FIND_MAX_NODES;
+
+        match dbg.get_locals(None, depth) {
+            Ok(locs) => for SqLocalVarWithLvl { var: SqLocalVar { name, val }, lvl } in locs {
+                Self::find_value(&val, &needle, format!("{lvl}.{name}"), &mut found, &mut budget);
+            }
+            Err(e) => println!("failed to get locals: {e}"),
+        }
+
+        if global {
+            match dbg.get_root_table(depth) {
+                Ok(root) => Self::find_value(&root, &needle, "::".into(), &mut found, &mut budget),
+                Err(e) => println!("failed to get root table: {e}"),
+            }
+        }
+
+        if budget == 0 {
+            println!("search stopped after {FIND_MAX_NODES} values, decrease depth to search everything");
+        }
+
+        if found.is_empty() {
+            return println!("value not found");
+        }
+
+        for path in found {
+            println!("{path}");
+        }
+    }
+
+    /// Evaluate expression in current function and compare with expected value
+    fn expect(&self, dbg: &dbg::SqDebugger, expr: &str, depth: usize) {
+        if self.during_eval {
+            println!("failed to evaluate: cannot evaluate during evaluation");
+            return;
+        }
+
+        let Some((actual, expected)) = Self::split_expectation(expr) else {
+            return println!("invalid expectation, must be in format `<expr> == <value>`");
+        };
+
+        // Both sides are evaluated by vm, so any squirrel literal may be used as value
+        let script = format!("return [({actual}), ({expected})];");
+
+        // Additional level for wrapping array
+        match dbg.execute(script, Self::frame_capture(dbg), depth + 1) {
+            Ok(DynSqVar::Array(v)) if v.len() == 2 => if v[0].deep_eq(&v[1]) {
+                println!("PASS");
+            } else {
+                println!("FAIL");
+                println!("  actual:   {}", v[0]);
+                println!("  expected: {}", v[1]);
+            }
+            Ok(res) => println!("failed to evaluate: unexpected result {res}"),
+            Err(e) => println!("failed to evaluate: {e}"),
+        }
+    }
+
+    /// Step once and wait until events loop handled the event vm halted at
+    fn step_wait(&self, dbg: &dbg::SqDebugger) -> Result<(), &'static str> {
+        let handled = self.halt_events.load(atomic::Ordering::Acquire);
+        dbg.step();
+
+        let mut waited = 0;
+        while self.halt_events.load(atomic::Ordering::Acquire) == handled {
+            if waited >= STEP_WHILE_WAIT_MS {
+                return Err("execution did not halt");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            waited += 10;
+        }
+        Ok(())
+    }
+
+    /// Step until predicate evaluated in current frame is true
+    fn step_while_not(&self, dbg: &dbg::SqDebugger, expr: &str, max: usize) {
+        if self.during_eval {
+            return println!("failed to step: cannot step during evaluation");
+        }
+
+        let script = format!("return !!({expr});");
+        for _ in 0..max {
+            if let Err(e) = self.step_wait(dbg) {
+                return println!("failed to step: {e}");
+            }
+
+            match dbg.execute(script.clone(), Self::frame_capture(dbg), 1) {
+                Ok(DynSqVar::Bool(true)) => return println!("condition reached"),
+                Ok(_) => (),
+                Err(e) => return println!("failed to evaluate condition: {e}"),
+            }
+        }
+
+        println!("condition not reached in {max} steps");
+    }
+
+    /// Step `count` times, printing only last event
+    fn step_count(&self, dbg: &dbg::SqDebugger, count: usize) {
+        if self.during_eval {
+            return println!("failed to step: cannot step during evaluation");
+        }
+
+        let mut done = 0;
+        let early = loop {
+            if done == count {
+                break None;
+            }
+
+            // Last step event is printed as usual
+            self.quiet_step.store(done + 1 < count, atomic::Ordering::Relaxed);
+            if let Err(e) = self.step_wait(dbg) {
+                break Some(e);
+            }
+            done += 1;
+
+            // Reason is set by events loop for this step's event
+            if let StopReason::Breakpoint(_) = *self.stop_reason.read().unwrap() {
+                if done < count {
+                    break Some("breakpoint reached");
+                }
+            }
+        };
+
+        // Step that did not halt in time is reported, when it does
+        self.quiet_step.store(false, atomic::Ordering::Relaxed);
+        match early {
+            Some(reason) => println!("stepped {done} of {count} times: {reason}"),
+            None => println!("stepped {done} times"),
+        }
+    }
+
+    /// Step and examine path until non-empty command entered.
+    /// Returns entered command
+    fn interactive_step(&self, dbg: &dbg::SqDebugger, path: &str) -> Option<String> {
+        if self.during_eval {
+            println!("failed to step: cannot step during evaluation");
+            return None;
+        }
+
+        let mut input = String::new();
+        println!("press Enter to step, enter any command to exit");
+
+        loop {
+            if let Err(e) = self.step_wait(dbg) {
+                println!("failed to step: {e}");
+                return None;
+            }
+
+            Self::examine(dbg, path, self.current_frame(), 1, &[], false, false, false, SqIntFormat::Dec);
+
+            input.clear();
+            if std::io::stdin().read_line(&mut input).is_err() {
+                return None;
+            }
+
+            if !input.trim().is_empty() {
+                return Some(input);
+            }
+        }
+    }
+
+    /// Get hit breakpoints, most hit first
+    fn hit_breakpoints(dbg: &dbg::SqDebugger) -> Vec<SqBreakpoint> {
+        let mut hit: Vec<_> = dbg.breakpoints().breakpoints().iter()
+            .filter(|bp| bp.hits > 0)
+            .cloned()
+            .collect();
+        hit.sort_by_key(|bp| std::cmp::Reverse(bp.hits));
+        hit
+    }
+
+    fn print_hits(dbg: &dbg::SqDebugger) {
+        const BP_NUMBER_FIELD: usize = 8;
+        const BP_HITS_FIELD: usize = 8;
+
+        let hit = Self::hit_breakpoints(dbg);
+        if hit.is_empty() {
+            return println!("no breakpoints were hit");
+        }
+
+        println!("{:<BP_NUMBER_FIELD$}{:<BP_HITS_FIELD$}location", "number", "hits");
+        for bp in hit {
+            println!("{:<BP_NUMBER_FIELD$}{:<BP_HITS_FIELD$}{}", bp.number, bp.hits, BrkSpec::from(bp.clone()));
+        }
+    }
+
+    fn disable_noisiest(dbg: &dbg::SqDebugger) {
+        let Some(bp) = Self::hit_breakpoints(dbg).into_iter().find(|bp| bp.enabled) else {
+            return println!("no enabled breakpoints were hit");
+        };
+
+        dbg.breakpoints().enable(Some(bp.number), false);
+        println!("disabled breakpoint {} ({} hits): {}", bp.number, bp.hits, BrkSpec::from(bp.clone()));
+    }
+
+    /// Verify breakpoints with source files. If `summary`, print only unbindable ones and counts
+    fn verify_breakpoints(&self, dbg: &dbg::SqDebugger, summary: bool) {
+        const BP_NUMBER_FIELD: usize = 8;
+        const BP_STATUS_FIELD: usize = 12;
+
+        if self.srcs.dirs().is_empty() {
+            if !summary {
+                println!("no source directories registered, can't verify breakpoints");
+            }
+            return;
+        }
+
+        let breakpoints = dbg.breakpoints().breakpoints().clone();
+        if breakpoints.is_empty() {
+            if !summary {
+                println!("no breakpoints");
+            }
+            return;
+        }
+
+        if !summary {
+            println!("{:<BP_NUMBER_FIELD$}{:<BP_STATUS_FIELD$}location", "number", "status");
+        }
+
+        let mut unbindable = 0;
+        for bp in &breakpoints {
+            let spec = BrkSpec::from(bp.clone());
+            match self.srcs.verify(&spec) {
+                Ok(()) if summary => (),
+                Ok(()) => println!("{:<BP_NUMBER_FIELD$}{:<BP_STATUS_FIELD$}{spec}", bp.number, "OK"),
+                Err(reason) => {
+                    unbindable += 1;
+                    println!("{:<BP_NUMBER_FIELD$}{:<BP_STATUS_FIELD$}{spec}: {reason}", bp.number, "UNBINDABLE");
+                }
+            }
+        }
+
+        if summary {
+            println!("breakpoints verified: {} ok, {unbindable} unbindable", breakpoints.len() - unbindable);
+        }
+    }
+
+    /// Print state of debugger message queue
+    fn print_queue(dbg: &dbg::SqDebugger) {
+        let (pending, awaiting) = dbg.queue_state();
+        println!("pending messages: {pending}, awaiting response: {awaiting}");
+
+        if pending > 0 && !awaiting && dbg.exec_state() == dbg::ExecState::Halted {
+            println!("warning: messages are pending while vm is halted, debugger may be out of sync");
+        }
+    }
+
+    /// Replace debugger with one attached to new vm, if any
+    fn reattach(&mut self, dbg: &mut dbg::SqDebugger) {
+        if self.during_eval {
+            return println!("failed to reattach: cannot reattach during evaluation");
+        }
+
+        // Debugger of newly bound vm waits there until taken
+        let Some(new) = hooks::SQ_DEBUGGER.lock().unwrap().take() else {
+            return println!("no new vm available yet, try again later");
+        };
+
+        self.replace_debugger(dbg, new);
+        println!("reattached to new vm, {} breakpoints restored", dbg.breakpoints().breakpoints().len());
+    }
+
+    /// Let vm run without debugger. Next bound vm is attached by prompt loop or `reattach`
+    fn detach(&mut self, dbg: &mut dbg::SqDebugger) {
+        if self.during_eval {
+            return println!("failed to detach: cannot detach during evaluation");
+        }
+
+        dbg.detach_hooks();
+        self.detached = true;
+        println!("detached, new vm will be attached when bound, see `help reattach`");
+    }
+
+    /// Attach to newly bound vm if detached, without waiting for it
+    fn attach_bound(&mut self, dbg: &mut dbg::SqDebugger) {
+        if !self.detached {
+            return;
+        }
+
+        if let Some(new) = hooks::SQ_DEBUGGER.lock().unwrap().take() {
+            self.replace_debugger(dbg, new);
+            println!("attached to new vm, {} breakpoints restored", dbg.breakpoints().breakpoints().len());
+        }
+    }
+
+    /// Move breakpoints to `new` debugger and detach the old one
+    fn replace_debugger(&mut self, dbg: &mut dbg::SqDebugger, new: dbg::SqDebugger) {
+        new.set_breakpoints(dbg.breakpoints().clone());
+        new.set_halt_depth(dbg.halt_depth());
+        let old = std::mem::replace(dbg, new);
+
+        // Old vm may be still alive and is owned by the game, let it run freely
+        std::mem::forget(old.detach());
+
+        *self.event_rx.write().unwrap() = dbg.event_rx().clone();
+        *self.last_event.write().unwrap() = BrkSpec::default();
+        self.detached = false;
+    }
+
+    /// Run vm for `secs` and report time spent in debug hook
+    fn measure_overhead(dbg: &dbg::SqDebugger, secs: u64) {
+        let (time_before, calls_before) = dbg.hook_stats();
+        let started = Instant::now();
+
+        dbg.resume();
+        std::thread::sleep(Duration::from_secs(secs));
+        dbg.halt();
+
+        let wall = started.elapsed();
+        let (time_after, calls_after) = dbg.hook_stats();
+        let (time, calls) = (time_after - time_before, calls_after - calls_before);
+
+        if calls == 0 {
+            return println!("no debug events received, vm is idle");
+        }
+
+        println!("debug events: {calls} in {wall:.1?}");
+        println!("time per event: {:.1?}", Duration::from_nanos(time.as_nanos() as u64 / calls));
+        println!(
+            "time in hook: {time:.1?} ({:.1}% of vm thread time)", 
+            time.as_secs_f64() / wall.as_secs_f64() * 100.0
+        );
+    }
+
+    fn print_errors(dbg: &dbg::SqDebugger, count: Option<usize>) {
+        let errors = dbg.errors();
+        if errors.is_empty() {
+            return println!("no errors recorded");
+        }
+
+        let skip = errors.len().saturating_sub(count.unwrap_or(errors.len()));
+        for (num, err) in errors.iter().enumerate().skip(skip) {
+            println!("Error {}: {}", num + 1, err.error);
+            Self::print_frames(&err.backtrace);
+        }
+    }
+
+    /// Run `f` with hook response timeout temporarily set to `timeout` seconds
+    fn with_timeout<R>(dbg: &dbg::SqDebugger, timeout: Option<u64>, f: impl FnOnce() -> R) -> R {
+        let Some(timeout) = timeout else {
+            return f();
+        };
+
+        let prev = dbg.timeout();
+        dbg.set_timeout(Duration::from_secs(timeout));
+        let res = f();
+        dbg.set_timeout(prev);
+        res
+    }
+
+    /// Get CLI parser
     fn cli() -> Command {
         // strip out usage
         const PARSER_TEMPLATE: &str = "\
             {all-args}
         ";
 
-        Commands::augment_subcommands(
-            Command::new("repl")
-                .multicall(true)
-                .arg_required_else_help(false)
-                .subcommand_required(true)
-                .subcommand_value_name("Command")
-                .subcommand_help_heading("Commands")
-                .help_template(PARSER_TEMPLATE)
-        )
+        Commands::augment_subcommands(
+            Command::new("repl")
+                .multicall(true)
+                .arg_required_else_help(false)
+                .subcommand_required(true)
+                .subcommand_value_name("Command")
+                .subcommand_help_heading("Commands")
+                .help_template(PARSER_TEMPLATE)
+        )
+    }
+
+    /// Set debugger variable
+    fn set_var(&mut self, dbg: &dbg::SqDebugger, var: &SetCommands) {
+        match var {
+            SetCommands::PrintfHook { active }
+                => hooks::PRINTF_HOOK_ACTIVE.store((*active).into(), atomic::Ordering::Relaxed),
+            SetCommands::MaxBuffers { count } => self.max_buffers = (*count).max(1),
+            SetCommands::DebugInfo { active } => dbg.set_debug_info((*active).into()),
+            SetCommands::OverflowWarn { active } 
+                => OVERFLOW_WARN.store((*active).into(), atomic::Ordering::Relaxed),
+            SetCommands::StrictSpecs { active } => self.strict_specs = (*active).into(),
+            SetCommands::StopOnError { active } => self.stop_on_error = (*active).into(),
+            SetCommands::Editor { program } if program.is_empty() => self.editor = None,
+            SetCommands::Editor { program } => self.editor = Some(program.clone()),
+            SetCommands::SourceDir { path }
+                => self.manipulate_sources(SrcCommands::Add { path: path.clone(), prefix: None }),
+            SetCommands::Timeout { secs } => dbg.set_timeout(Duration::from_secs((*secs).max(1))),
+            SetCommands::OutputFormat { mode } => self.output_format = *mode,
+        }
+    }
+
+    /// Collapse container children to type-only values, except ones with keys from `only`
+    fn collapse_except(val: &DynSqVar, only: &[String]) -> DynSqVar {
+        let keep = |key: &DynSqVar| match key {
+            DynSqVar::String(s) => only.contains(s),
+            DynSqVar::Integer(i) => only.contains(&i.to_string()),
+            _ => false,
+        };
+
+        let collapse = |key: &DynSqVar, val: &DynSqVar| if keep(key) {
+            val.clone()
+        } else {
+            DynSqVar::NotExpanded(val.get_type())
+        };
+
+        let collapse_map = |map: &SqTable| map.iter()
+            .map(|(k, v)| (k.clone(), collapse(k, v)))
+            .collect();
+
+        match val {
+            DynSqVar::Table(map) => DynSqVar::Table(collapse_map(map)),
+            DynSqVar::Class(map) => DynSqVar::Class(collapse_map(map)),
+            DynSqVar::Instance(SqInstance { this }) 
+                => DynSqVar::Instance(SqInstance { this: collapse_map(this) }),
+            DynSqVar::Array(v) => DynSqVar::Array(v.iter()
+                .enumerate()
+                .map(|(idx, v)| collapse(&DynSqVar::Integer(idx as isize), v))
+                .collect()
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Pretty-print local variable, try to find local by it's dot-separated path.
+    /// Returns printed text
+    #[allow(clippy::too_many_arguments)]
+    fn examine(
+        dbg: &dbg::SqDebugger, 
+        path: &str,
+        mut level: Option<usize>, 
+        mut depth: usize,
+        only: &[String],
+        as_source: bool,
+        into_closures: bool,
+        json: bool,
+        ints: SqIntFormat,
+    ) -> Option<String> {
+        use SqPathToken::*;
+        let segments: Result<Vec<SqPathToken>, ()> = SqPathToken::lexer(path)    
+            .filter_map(|s| match s {
+                Number(_) | NegIndex(_) | Slice(_) | Seg(_) | QuotedSeg(_) => Some(Ok(s)),
+                Error => Some(Err(())),
+                _ => None
+            })
+            .collect();
+
+        let segments = match segments {
+            Ok(segs) if segs.is_empty() => {
+                println!("path is empty");
+                return None
+            }
+            Ok(segs) => segs,
+            Err(_) => {
+                println!("path is invalid");
+                return None
+            }
+        };
+
+        let (root_name, seg_cnt) = match &segments[..2.min(segments.len())] {
+            // Check if first path segment is call stack level
+            [Number(lvl), root @ (Seg(_) | QuotedSeg(_))] => {
+                level = Some(*lvl);
+                (root.text().unwrap(), segments.len() - 2)
+            },
+            
+            [root @ (Seg(_) | QuotedSeg(_))]
+            | [root @ (Seg(_) | QuotedSeg(_)), ..] => (root.text().unwrap(), segments.len() - 1),
+            
+            [Number(_)] => {
+                println!("Local path not specified, only call stack level");
+                return None
+            }
+            
+            // Level followed by index or slice, e.g. `2.-1` or `2.1:3`, names no local
+            _ => {
+                println!("path is invalid");
+                return None
+            }
+        };
+
+        // Target itself is expanded, listed children get requested depth
+        if !only.is_empty() {
+            depth += 1;
+        }
+
+        if into_closures {
+            depth += 1;
+        }
+
+        let print_target = |name: &str, target: &DynSqVar| {
+            let target = if only.is_empty() {
+                target.clone()
+            } else {
+                Self::collapse_except(target, only)
+            };
+            let text = if json {
+                Self::json_value(name, &target)
+            } else if as_source {
+                target.as_source().to_string()
+            } else {
+                format!("{name}: {typ:?} = {}", target.formatted(into_closures, ints), typ = target.get_type())
+            };
+            println!("{text}");
+            if !json {
+                Self::warn_overflow(&target, name);
+            }
+            text
+        };
+        
+        // Only containers on the path are fetched, siblings are not expanded
+        let vm_path = std::iter::once(SqPathKey::Name(root_name.clone()))
+            .chain(segments.iter().skip(segments.len() - seg_cnt).filter_map(SqPathToken::key))
+            .collect();
+        
+        match dbg.get_local_child(level, vm_path, depth, into_closures) {
+            Ok(target) if seg_cnt == 0 => Some(print_target(&root_name, &target)),
+            Ok(target) => Some(print_target(path, &target)),
+            Err(e) => {
+                println!("failed to match path `{path}`: {e}");
+                None
+            }
+        }
+    }
+
+    /// Print current function, location, stop reason and `this` type in one line
+    fn print_where(&self, dbg: &dbg::SqDebugger) {
+        let frame = match dbg.get_backtrace() {
+            Ok(bt) => match bt.into_iter().next() {
+                Some(frame) => frame,
+                None => return println!("call stack is empty"),
+            },
+            Err(e) => return println!("failed to get backtrace: {e}"),
+        };
+
+        // Do not expand `this`, only type is needed
+        let this = dbg.get_locals(Some(1), 0).ok()
+            .and_then(|locs| locs.into_iter().find(|l| l.var.name == "this"))
+            .map_or("??".into(), |l| format!("{:?}", l.var.val.get_type()));
+
+        println!("{func}() @ {src}:{line} ({reason}) this={this}",
+            func = frame.funcname.as_deref().unwrap_or("??"),
+            src = frame.src_file.as_deref().unwrap_or("??"),
+            line = frame.line.map_or("??".into(), |l| l.to_string()),
+            reason = self.stop_reason.read().unwrap(),
+        );
+    }
+
+    /// Find compiled function at call stack level or by name.
+    /// Function of level is matched by its name, source file and current line
+    fn find_function_proto(&self, dbg: &dbg::SqDebugger, target: Option<&str>) -> Result<SqFunctionProto> {
+        let level = match target {
+            None => Some(self.current_frame().unwrap_or(1)),
+            Some(t) => t.parse::<usize>().ok(),
+        };
+
+        let protos = dbg.get_function_protos()?;
+        let mut found: Vec<_> = match (level, target) {
+            (Some(level), _) => {
+                let Some(frame) = dbg.get_backtrace()?.into_iter().nth(level.saturating_sub(1)) else {
+                    bail!("no function at level {level}");
+                };
+                protos.into_iter()
+                    .filter(|proto| proto.name == frame.funcname && proto.src == frame.src_file)
+                    .filter(|proto| matches!((proto.line_range(), frame.line), 
+                        (Some((first, last)), Some(line)) if (first..=last).contains(&line)
+                    ))
+                    .collect()
+            }
+            (None, Some(name)) => protos.into_iter()
+                .filter(|proto| proto.name.as_deref() == Some(name))
+                .collect(),
+            (None, None) => unreachable!(),
+        };
+
+        match found.len() {
+            0 => bail!("function is not found among closures reachable from root table"),
+            1 => Ok(found.remove(0)),
+            _ => {
+                for proto in &found {
+                    let line = proto.line_range().map(|(first, _)| first.to_string());
+                    println!("{}:{}", proto.src.as_deref().unwrap_or("?"), line.as_deref().unwrap_or("?"));
+                }
+                bail!("multiple functions match, select one by call stack level")
+            }
+        }
+    }
+
+    /// Print line info table of function, sorted by instruction offset
+    fn print_line_info(&self, dbg: &dbg::SqDebugger, target: Option<&str>) {
+        const OFFSET_FIELD: usize = 8;
+
+        let proto = match self.find_function_proto(dbg, target) {
+            Ok(proto) => proto,
+            Err(e) => return println!("failed to get function: {e}"),
+        };
+
+        let name = proto.name.as_deref().unwrap_or("<anonymous>");
+        println!("{name} in {}:", proto.src.as_deref().unwrap_or("?"));
+        if proto.lines.is_empty() {
+            return println!("function has no line info");
+        }
+
+        println!("{:<OFFSET_FIELD$}line", "offset");
+        for (offset, line) in &proto.lines {
+            println!("{offset:<OFFSET_FIELD$}{line}");
+        }
+    }
+
+    /// Print closure signature at call stack level
+    fn print_signature(dbg: &dbg::SqDebugger, level: usize) {
+        match dbg.get_frame_closure(level) {
+            Ok(DynSqVar::Closure(info)) => {
+                println!("{}", DynSqVar::Closure(info.clone()));
+                if let Some(src) = info.src {
+                    println!("defined in {src}");
+                }
+            }
+            Ok(DynSqVar::NativeClosure(SqNativeClosureInfo { name, arg_types })) => println!(
+                "{}: <native, {} args>", name.as_deref().unwrap_or("fn"), arg_types.len()
+            ),
+            Ok(other) => println!("not a function: {other}"),
+            Err(e) => println!("failed to get signature: {e}"),
+        }
+    }
+
+    /// Print counts of function calls, most called first
+    fn print_call_counts(dbg: &dbg::SqDebugger, count: Option<usize>) {
+        const CALLS_FIELD: usize = 12;
+
+        let profiler = dbg.profiler();
+        let counts = profiler.call_counts();
+        if counts.is_empty() {
+            return println!("no calls counted, see `help profile`");
+        }
+
+        println!("{:<CALLS_FIELD$}function", "calls");
+        for (name, calls) in counts.into_iter().take(count.unwrap_or(usize::MAX)) {
+            println!("{calls:<CALLS_FIELD$}{name}");
+        }
+    }
+
+    /// Print time spent in functions, slowest first
+    fn print_function_times(dbg: &dbg::SqDebugger, count: Option<usize>) {
+        const TIME_FIELD: usize = 14;
+
+        let profiler = dbg.profiler();
+        let times = profiler.function_times();
+        if times.is_empty() {
+            return println!("no function times measured, see `help profile-time`");
+        }
+
+        println!("{:<TIME_FIELD$}function", "time");
+        for (name, time) in times.into_iter().take(count.unwrap_or(usize::MAX)) {
+            println!("{:<TIME_FIELD$}{name}", format!("{time:.3?}"));
+        }
+    }
+
+    /// Write covered lines as json or lcov tracefile, depending on extension of path
+    fn write_coverage(dbg: &dbg::SqDebugger, path: &str) {
+        let profiler = dbg.profiler();
+        let covered = profiler.covered_lines();
+        if covered.is_empty() {
+            return println!("no lines covered, see `help coverage`");
+        }
+
+        let report = if Path::new(path).extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_string_pretty(&covered).unwrap()
+        } else {
+            let mut report = String::new();
+            for (src, lines) in &covered {
+                writeln!(report, "SF:{src}").unwrap();
+                for line in lines {
+                    writeln!(report, "DA:{line},1").unwrap();
+                }
+                writeln!(report, "LH:{0}\nLF:{0}\nend_of_record", lines.len()).unwrap();
+            }
+            report
+        };
+
+        if let Err(e) = std::fs::write(path, report) {
+            println!("failed to write coverage: {e}");
+        }
+    }
+
+    /// Print stack info of frame at call stack level
+    fn print_frame_info(dbg: &dbg::SqDebugger, level: usize) {
+        let bt = match dbg.get_backtrace() {
+            Ok(bt) => bt,
+            Err(e) => return println!("failed to get backtrace: {e}"),
+        };
+
+        let Some(SqStackInfo { funcname, src_file, line }) = level.checked_sub(1).and_then(|i| bt.get(i)) else {
+            return println!("no frame at level {level}, call stack has {} levels", bt.len());
+        };
+        let or_unknown = |s: &Option<String>| s.clone().unwrap_or_else(|| "??".into());
+        let line_or_unknown = |l: &Option<isize>| l.map_or("??".into(), |l| l.to_string());
+
+        println!("frame {level:03}:");
+        println!("  function: {}", or_unknown(funcname));
+        println!("  source: {}", or_unknown(src_file));
+        println!("  line: {}", line_or_unknown(line));
+        // Current line of caller frame is the call site
+        match bt.get(level) {
+            Some(caller) => println!("  called from: {}:{} ({})",
+                or_unknown(&caller.src_file), line_or_unknown(&caller.line), or_unknown(&caller.funcname),
+            ),
+            None => println!("  called from: <native>"),
+        }
+        match dbg.get_locals(Some(level), 0) {
+            Ok(locals) => println!("  locals: {}", locals.len()),
+            Err(e) => println!("  locals: failed to get: {e}"),
+        }
+    }
+
+    /// Split path into slot keys
+    fn split_path(path: &str) -> Option<Vec<SqPathKey>> {
+        use SqPathToken::*;
+        let mut segments = vec![];
+        for token in SqPathToken::lexer(path) {
+            match token {
+                Dot => (),
+                Error => return None,
+                seg => segments.extend(seg.key()),
+            }
+        }
+        Some(segments)
+    }
+
+    /// Split local path into call stack level and slot keys.
+    /// If path is not prefixed with level, `level` is used
+    fn split_local_path(path: &str, mut level: usize) -> Option<(usize, Vec<SqPathKey>)> {
+        let mut segments = Self::split_path(path)?;
+
+        // Check if first path segment is call stack level
+        if let [SqPathKey::Index(lvl), _, ..] = segments.as_slice() {
+            if let Ok(lvl) = usize::try_from(*lvl) {
+                level = lvl;
+                segments.remove(0);
+            }
+        }
+        Some((level, segments))
+    }
+
+    /// Set scalar at local path to parsed value
+    fn set_local(dbg: &dbg::SqDebugger, path: &str, value: &str) {
+        let Some((level, path)) = Self::split_local_path(path, 1) else {
+            return println!("path is invalid");
+        };
+
+        let value = if let Ok(i) = value.parse() {
+            DynSqVar::Integer(i)
+        } else if let Ok(f) = value.parse() {
+            DynSqVar::Float(f)
+        } else if let Ok(b) = value.parse() {
+            DynSqVar::Bool(b)
+        } else if let Some(s) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            DynSqVar::String(s.to_string())
+        } else {
+            return println!("value must be integer, float, bool or quoted string");
+        };
+
+        if let Err(e) = dbg.set_local(level, path, value) {
+            println!("failed to set value: {e}");
+        }
+    }
+
+    /// Print type tag and bytes of object at local path
+    fn print_raw(dbg: &dbg::SqDebugger, path: &str, level: usize) {
+        let Some((level, segments)) = Self::split_local_path(path, level) else {
+            return println!("path is invalid");
+        };
+
+        let raw = match dbg.get_local_raw(level, segments) {
+            Ok(raw) => raw,
+            Err(e) => return println!("failed to get raw value: {e}"),
+        };
+
+        let bytes = raw.bytes.chunks(4)
+            .map(|word| word.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        println!("internal representation, layout depends on sqvm build");
+        println!("tag:   0x{:08X} ({:?})", raw.tag, SqType::from(raw.tag));
+        println!("bytes: {bytes}");
+        println!("value: {}", raw.val);
+    }
+
+    /// Print parameters of closure at call stack level or returned by expression
+    fn print_defaults(&self, dbg: &dbg::SqDebugger, target: Option<&str>) {
+        const PARAM_FIELD: usize = 20;
+
+        let closure = match target.map(|t| (t, t.parse::<usize>())) {
+            None => dbg.get_frame_closure(1),
+            Some((_, Ok(level))) => dbg.get_frame_closure(level),
+            Some((expr, Err(_))) => {
+                if self.during_eval {
+                    return println!("failed to evaluate: cannot evaluate during evaluation");
+                }
+                dbg.execute(Self::wrap_expression(expr), Self::frame_capture(dbg), 1)
+            }
+        };
+
+        let SqClosureInfo { args, defparams, varargs, .. } = match closure {
+            Ok(DynSqVar::Closure(info)) => info,
+            Ok(DynSqVar::NativeClosure(_)) => return println!("native functions have no default values"),
+            Ok(other) => return println!("not a function: {other}"),
+            Err(e) => return println!("failed to get function: {e}"),
+        };
+
+        // Default values are bound to the last arguments
+        let first_def = args.len().saturating_sub(defparams.len());
+
+        println!("{:<PARAM_FIELD$}default", "parameter");
+        for (idx, arg) in args.iter().enumerate() {
+            match idx.checked_sub(first_def).and_then(|i| defparams.get(i)) {
+                Some(def) => println!("{arg:<PARAM_FIELD$}{def}"),
+                None => println!("{arg:<PARAM_FIELD$}<required>"),
+            }
+        }
+        if varargs {
+            println!("{:<PARAM_FIELD$}<variable>", "...");
+        }
+    }
+
+    /// Parse breakpoint specification 
+    fn add_breakpoint(
+        dbg: &dbg::SqDebugger, 
+        spec: &str, 
+        condition: &[String], 
+        snapshot: bool,
+        temporary: bool,
+    ) -> Result<()> {
+        let Ok(parsed) = BrkSpec::parse(spec) else {
+            bail!("failed to parse specification `{spec}`");
+        };
+
+        let (ignore_count, condition) = match condition {
+            [kw, count, rest @ ..] if kw == "ignore" => match count.parse() {
+                Ok(count) => (count, rest),
+                Err(_) => bail!("invalid ignore count `{count}`"),
+            }
+            rest => (0, rest),
+        };
+
+        // After condition message must be quoted, so `log` in expression is not a keyword
+        let log_pos = condition.iter().enumerate().position(|(pos, w)| w == "log" 
+            && (pos == 0 || condition.get(pos + 1).is_some_and(|m| m.starts_with('"'))));
+        let (condition, log) = match log_pos {
+            Some(pos) => (&condition[..pos], Some(&condition[pos + 1..])),
+            None => (condition, None),
+        };
+
+        let condition = match condition.split_first() {
+            None => None,
+            Some((kw, expr)) if kw == "if" && !expr.is_empty() => Some(expr.join(" ")),
+            Some(_) => bail!("breakpoint options must be in format `[ignore <n>] [if <expr>] [log <template>]`"),
+        };
+
+        let log = match log {
+            None => None,
+            Some([]) => bail!("logpoint message is empty"),
+            Some(words) => {
+                let msg = words.join(" ");
+                // Quotes are optional
+                let unquoted = msg.strip_prefix('"').and_then(|m| m.strip_suffix('"'));
+                Some(unquoted.map_or(msg.clone(), str::to_string))
+            }
+        };
+        
+        dbg.breakpoints().add(SqBreakpoint { snapshot, condition, ignore_count, temporary, log, ..parsed.into() });
+        Ok(())
     }
 
-    /// Set debugger variable
-    fn set_var(var: &SetCommands) {
-        match var {
-            SetCommands::PrintfHook { active }
-                => hooks::PRINTF_HOOK_ACTIVE.store((*active).into(), atomic::Ordering::Relaxed),
-        }
+    /// Get selected call stack level, if any
+    fn current_frame(&self) -> Option<usize> {
+        self.frame.map(|(lvl, _)| lvl)
     }
 
-    /// Match dot-separated path in container recursively
-    fn match_local_path<'a, I>(mut path: I, root: &DynSqVar) -> Option<&DynSqVar>
-    where I: Iterator<Item = &'a SqPathToken<'a>> + Clone {
-        use SqPathToken::*;
-        
-        let Some(key) = path.next() else {
-            return None;
+    /// Select call stack level, or print selected one if `num` is `None`
+    fn select_frame(&mut self, dbg: &dbg::SqDebugger, num: Option<usize>) {
+        let bt = match dbg.get_backtrace() {
+            Ok(bt) => bt,
+            Err(e) => return println!("failed to get backtrace: {e}"),
         };
 
-        let child = match root {
-            DynSqVar::Table(map)
-            | DynSqVar::Class(map)
-            | DynSqVar::Instance(SqInstance { this: map }) => match key {
-                Seg(seg) | QuotedSeg(seg) => {
-                    map.iter().find(|(k, _)| {
-                        matches!(k, DynSqVar::String(s) if s == seg)
-                    })
-                }
-                Number(idx) => {
-                    map.iter().find(|(k, _)| {
-                        matches!(k, DynSqVar::Integer(i) if *i == *idx as isize)
-                    })
-                }
-                _ => None,
-            }.map(|(_, v)| v),
+        let lvl = num.or(self.current_frame()).unwrap_or(1);
+        let Some(info) = lvl.checked_sub(1).and_then(|i| bt.get(i)) else {
+            return println!("no frame at level {lvl}, call stack has {} levels", bt.len());
+        };
 
-            DynSqVar::Array(v) => match key {
-                Number(idx) => v.get(*idx),
-                _ => None
+        if num.is_some() {
+            self.frame = Some((lvl, self.event_log.lock().unwrap().end()));
+        }
+        println!("{lvl:03}: {info}");
+    }
+
+    /// Select innermost frame again, if new event was received since selection
+    fn reset_frame(&mut self) {
+        if let Some((_, at)) = self.frame {
+            if at != self.event_log.lock().unwrap().end() {
+                self.frame = None;
             }
-            
-            _ => None,
+        }
+    }
+
+    /// Resume until location is reached, without user-visible breakpoint left behind
+    fn run_until(&mut self, dbg: &dbg::SqDebugger, spec: &str) {
+        let Ok(parsed) = BrkSpec::parse(spec) else {
+            return println!("failed to parse specification `{spec}`");
+        };
+
+        let mut breakpoints = dbg.breakpoints();
+        breakpoints.reset_hits();
+        self.until_bp = Some(breakpoints.add(SqBreakpoint { temporary: true, ..parsed.into() }));
+        drop(breakpoints);
+        dbg.resume();
+    }
+
+    /// Print error, if execution was halted by it
+    fn report_error_halt(&mut self, dbg: &dbg::SqDebugger) {
+        let Some(err) = dbg.take_error_halt() else {
+            return;
         };
 
-        // Peek if path has next segment
-        match (child, path.clone().next()) {
-            (Some(_), None) => child,
-            (Some(next), Some(_)) => Self::match_local_path(path, next),
-            _ => None
+        *self.stop_reason.write().unwrap() = StopReason::Error;
+        println!("halted on error: {}", err.error);
+        Self::print_frames(&err.backtrace);
+    }
+
+    /// Print changes of watched values and removed watchpoints since last halt
+    fn report_watchpoints(&mut self, dbg: &dbg::SqDebugger) {
+        for event in dbg.watchpoints().take_events() {
+            match event {
+                WatchpointEvent::Changed { number, path, old, new } => {
+                    *self.stop_reason.write().unwrap() = StopReason::Watchpoint(number);
+                    println!("watchpoint {number}: {} changed", format_path(&path));
+                    println!("  old: {old}");
+                    println!("  new: {new}");
+                }
+                WatchpointEvent::OutOfScope { number, path }
+                    => println!("watchpoint {number} removed: {} is out of scope", format_path(&path)),
+            }
         }
     }
 
-    // TODO: Make lazily evaluated containers
-    /// Pretty-print local variable, try to find local by it's dot-separated path
-    fn examine(
-        dbg: &dbg::SqDebugger, 
-        path: &str,
-        mut level: Option<usize>, 
-        mut depth: usize
-    ) {
-        use SqPathToken::*;
-        let segments: Result<Vec<SqPathToken>, ()> = SqPathToken::lexer(path)    
-            .filter_map(|s| match s {
-                Number(_) | Seg(_) | QuotedSeg(_) => Some(Ok(s)),
-                Error => Some(Err(())),
-                _ => None
-            })
-            .collect();
+    /// Remove breakpoint of `until`, if execution was halted by something else
+    fn clear_until(&mut self, dbg: &dbg::SqDebugger) {
+        if let Some(num) = self.until_bp.take() {
+            dbg.breakpoints().remove(Some(num));
+        }
+    }
 
-        let segments = match segments {
-            Ok(segs) if segs.is_empty() => return println!("path is empty"),
-            Ok(segs) => segs,
-            Err(_) => return println!("path is invalid"),
+    /// Report depth trigger, if it was fired on this halt
+    fn check_depth_trigger(&mut self, dbg: &dbg::SqDebugger) {
+        let Some(depth) = self.armed_depth else {
+            return;
+        };
+        if dbg.halt_depth().is_some() {
+            return;
+        }
+
+        self.armed_depth = None;
+        *self.stop_reason.write().unwrap() = StopReason::Depth(depth);
+
+        let func = self.last_event.read().unwrap().func.clone();
+        println!("reached call stack depth {depth} in {}", func.as_deref().unwrap_or("??"));
+    }
+
+    /// Save snapshots captured by hits of breakpoints with snapshot option
+    fn save_hit_snapshots(&mut self, dbg: &dbg::SqDebugger) {
+        let uptime = dbg.uptime().0;
+        for HitSnapshot { number, received, locals } in self.hit_snapshots.lock().unwrap().drain(..) {
+            let name = format!("bp{number}@{:.3}s", uptime.saturating_sub(received.elapsed()).as_secs_f64());
+            let locals = locals.into_iter().map(|var| SqLocalVarWithLvl { var, lvl: 1 });
+            self.snapshots.insert(name, Self::locals_table(locals));
+        }
+    }
+
+    /// Add breakpoint at last received line event location
+    fn break_here(&self, dbg: &dbg::SqDebugger) {
+        let last = self.last_event.read().unwrap();
+        let (Some(file), Some(line)) = (&last.file, last.line) else {
+            return println!("current line is unknown, step to any line first");
         };
 
-        let (root_name, seg_cnt) = match &segments[..2.min(segments.len())] {
-            // Check if first path segment is call stack level
-            [Number(lvl), Seg(root) | QuotedSeg(root)] => {
-                level = Some(*lvl);
-                (*root, segments.len() - 2)
-            },
-            
-            [Seg(root) | QuotedSeg(root)]
-            | [Seg(root) | QuotedSeg(root), ..] => (*root, segments.len() - 1),
-            
-            [Number(_)] => {
-                println!("Local path not specified, only call stack level");
-                return
-            }
-            
-            _ => unreachable!(),
+        let spec = BrkSpec { file: Some(file.clone()), func: None, line: Some(line) };
+        let location = spec.to_string();
+        println!("breakpoint {} added at {location}", dbg.breakpoints().add(spec.into()));
+    }
+
+    /// Add breakpoints at all found call sites of function
+    fn break_callers(&self, dbg: &dbg::SqDebugger, func: &str) {
+        let protos = match dbg.get_function_protos() {
+            Ok(protos) => protos,
+            Err(e) => return println!("failed to get functions: {e}"),
         };
 
-        // Add minimal length needed to match path
-        depth += seg_cnt;
-        
-        let path_seg = segments.iter().skip(segments.len() - seg_cnt);
-        
-        match dbg.get_locals(level, depth) {
-            Ok(locs) => {
-                for SqLocalVarWithLvl { var: SqLocalVar { name, val }, ..} in locs {
-                    if root_name == name {
-                        // This is target
-                        if path_seg.clone().next().is_none() {
-                            println!("{name}: {typ:?} = {val}", typ = val.get_type());
-                            return;
-                        }
-                        // Try to recursively find target in children
-                        if let Some(target) = Self::match_local_path(path_seg.clone(), &val) {
-                            println!("{path}: {typ:?} = {target}", typ = target.get_type());
-                            return;
-                        }
-                    }
+        let mut callers: Vec<(Option<String>, usize)> = vec![];
+        let mut unresolved = 0;
+        let mut indirect = 0;
+        for proto in &protos {
+            indirect += proto.indirect_calls;
+            for (_, line) in proto.calls.iter().filter(|(name, _)| name == func) {
+                let Some(line) = line else {
+                    unresolved += 1;
+                    continue;
+                };
+                // Several calls on one line need one breakpoint
+                let site = (proto.src.clone(), *line as usize);
+                if !callers.contains(&site) {
+                    callers.push(site);
                 }
+            }
+        }
+
+        if unresolved > 0 {
+            println!("{unresolved} calls of `{func}` have no line info and are skipped");
+        }
+        if indirect > 0 {
+            println!("{indirect} calls through locals or expressions can't be checked statically");
+        }
+        if callers.is_empty() {
+            return println!("no calls of `{func}` found");
+        }
 
-                println!("failed to match path `{path}`");
+        let count = callers.len();
+        let mut breakpoints = dbg.breakpoints();
+        for (file, line) in callers {
+            let spec = BrkSpec { file, func: None, line: Some(line) };
+            let location = spec.to_string();
+            println!("breakpoint {} added at {location}", breakpoints.add(spec.into()));
+        }
+        println!("found {count} call sites of `{func}`");
+    }
 
-            },
+    /// Add new buffer, evicting old one if buffers limit is reached.
+    /// Returns added buffer number
+    fn new_buffer(&mut self, buf: String) -> Option<u32> {
+        match self.buffers.evict(self.max_buffers) {
+            Ok(Some(num)) => println!("buffers limit reached, buffer {num} evicted"),
+            Ok(None) => (),
             Err(e) => {
-                println!("failed to get locals: {e}");
-            },
+                println!("failed to add buffer: {e}");
+                return None;
+            }
         }
+
+        Some(self.buffers.add(buf))
     }
 
-    /// Parse breakpoint specification 
-    fn add_breakpoint(dbg: &dbg::SqDebugger, spec: &str) {
-        let spec = match BrkSpec::parse(spec) {
-            Ok(s) => s,
-            Err(_) => return println!("failed to parse specification"),
-        };
-        
-        dbg.breakpoints().add(spec.into());
+    /// Get editor program followed by its arguments: configured one, then from environment, then default.
+    /// Environment value is split by whitespace, e.g. `code --wait`
+    fn editor(&self) -> Vec<String> {
+        let env = || std::env::var("VISUAL").ok()
+            .filter(|e| !e.trim().is_empty())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .map(|e| e.split_whitespace().map(String::from).collect());
+
+        self.editor.clone()
+            .or_else(env)
+            .filter(|e: &Vec<String>| !e.is_empty())
+            .unwrap_or_else(|| vec![DEFAULT_EDITOR.into()])
     }
 
     /// Create or edit buffer
-    fn edit_buffer(prev: Option<&str>) -> Result<String> {
-        match scrawl::editor::new()
-            .editor("hx")
-            .extension(".nut")
-            .contents(prev.unwrap_or_default())
-            .open() 
-        {
+    fn edit_buffer(&self, prev: Option<&str>) -> Result<String> {
+        match self.open_editor(prev.unwrap_or_default()) {
             Ok(s) => Ok(s),
             // Try to open default editor
             Err(_) => Ok(scrawl::with(prev.unwrap_or_default())?),
         }
     }
 
+    /// Edit `contents` in temporary `.nut` file with editor from [Self::editor]
+    fn open_editor(&self, contents: &str) -> Result<String> {
+        let editor = self.editor();
+        let path = std::env::temp_dir().join(format!("sq-buffer-{}.nut", std::process::id()));
+        std::fs::write(&path, contents)?;
+
+        let status = std::process::Command::new(&editor[0])
+            .args(&editor[1..])
+            .arg(&path)
+            .status();
+        let res = match status {
+            Ok(status) if status.success() => Ok(std::fs::read_to_string(&path)?),
+            Ok(status) => Err(anyhow!("editor exited with {status}")),
+            Err(e) => Err(e.into()),
+        };
+        let _ = std::fs::remove_file(&path);
+        res
+    }
+
     /// Execute arbitrary script 
     fn eval_script(
         &mut self,
         dbg: &dbg::SqDebugger,
         debug: bool,
-        buffer: Option<u32>,
-        depth: usize
+        buffer: Option<&str>,
+        depth: usize,
+        snapshot: Option<&str>,
+        capture_output: bool,
     ) {
         if self.during_eval {
             println!("failed to evaluate: cannot evaluate during evaluation");
             return;
         }
 
-        let script = match buffer {
-            Some(num ) if self.buffers.get(num).is_some() => {
-                self.buffers.get(num).unwrap()
-            }, 
-            _ => match Self::edit_buffer(None) {
+        let values = match snapshot.map(|name| (name, self.snapshots.get(name))) {
+            Some((_, Some(snap))) => Self::snapshot_values(snap),
+            Some((name, None)) => return println!("no such snapshot: {name}"),
+            None => IndexMap::new(),
+        };
+
+        let script = match buffer.map(|b| self.buffers.resolve(b)) {
+            Some(Some(num)) => self.buffers.get(num).unwrap(),
+            Some(None) => return println!("no such buffer"),
+            None => match self.edit_buffer(None) {
                 Ok(s) => {
-                    let num = self.buffers.add(s);
+                    let Some(num) = self.new_buffer(s) else { return };
                     self.buffers.get(num).unwrap()
                 },
                 Err(e) => {
@@ -506,13 +2775,36 @@ impl DebuggerFrontend {
             },
         };
 
+        let Some((script, capture)) = Self::parse_capture_header(script) else { return };
+        self.run_script(dbg, script, capture, values, depth, debug, capture_output);
+    }
+
+    /// Execute script file without storing it in buffers
+    fn source_file(&mut self, dbg: &dbg::SqDebugger, path: &str, debug: bool, depth: usize) {
+        if self.during_eval {
+            println!("failed to evaluate: cannot evaluate during evaluation");
+            return;
+        }
+
+        let script = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => return println!("failed to read file: {e}"),
+        };
+
+        let Some((script, capture)) = Self::parse_capture_header(&script) else { return };
+        self.run_script(dbg, script, capture, IndexMap::new(), depth, debug, false);
+    }
+
+    /// Split script into list of captured locals `|lvl.name, ...|` and script itself.
+    /// Returns `None` and prints error if list is invalid
+    fn parse_capture_header(script: &str) -> Option<(String, Vec<dbg::SqCaptureLocal>)> {
         let mut lines = script.lines();
         // Parse list of captured local vars
         let (script, capture) = if let Some(mut line) = lines.next() { 'block: {
             line = line.trim();
             if !line.starts_with('|') || !line.ends_with('|') {
                 // Return cloned script and no captured locals
-                break 'block (script.clone(), vec![]);
+                break 'block (script.to_string(), vec![]);
             }
 
             let list = &line[1..line.len() - 1];
@@ -526,11 +2818,11 @@ impl DebuggerFrontend {
                         out.push((name.to_string(), lvl));
                     } else {
                         println!("invalid level specification: {lvl} ({spec})");
-                        return;
+                        return None;
                     }
                     _ => {
                         println!("invalid local var specification: {spec}");
-                        return;
+                        return None;
                     }
                 }
             }
@@ -538,43 +2830,449 @@ impl DebuggerFrontend {
             // Return script without first line and vector with captured vars
             (lines.collect(), out)
         }} else {
-            println!("buffer is empty");
+            println!("script is empty");
+            return None;
+        };
+
+        Some((script, capture))
+    }
+
+    /// Execute script and print result, or spawn thread to print it if debugging it
+    #[allow(clippy::too_many_arguments)]
+    fn run_script(
+        &mut self,
+        dbg: &dbg::SqDebugger,
+        script: String,
+        capture: Vec<dbg::SqCaptureLocal>,
+        values: IndexMap<String, DynSqVar>,
+        depth: usize,
+        debug: bool,
+        capture_output: bool,
+    ) {
+        self.during_eval = true;
+
+        let eval_res = |res| match res {
+            Ok(res) => {
+                println!("evaluation result: {res}");
+                Self::warn_overflow(&res, "result");
+            }
+            Err(e) => println!("failed to evaluate: {e}"),  
+        };
+
+        if capture_output {
+            *hooks::PRINT_CAPTURE.lock().unwrap() = Some(String::new());
+            let res = dbg.execute_with_values(script, capture, values, depth);
+            let output = hooks::PRINT_CAPTURE.lock().unwrap().take().unwrap_or_default();
+
+            eval_res(res);
+            println!("script output:");
+            print!("{output}");
+            if !output.is_empty() && !output.ends_with('\n') {
+                println!();
+            }
+        }
+        else if !debug {
+            eval_res(dbg.execute_with_values(script, capture, values, depth))
+        }
+        else { 
+            let fut = dbg.execute_debug(script, capture, depth);
+            
+            // Spawn thread that will wait for eval result
+            std::thread::spawn(move || eval_res(fut()));
+        }
+
+
+        self.during_eval = false;
+    }
+
+    /// Add, replace or remove (if `expansion` is empty) command alias
+    fn define_alias(&mut self, name: &str, expansion: &str) {
+        if Self::cli().find_subcommand(name).is_some() {
+            return println!("can't redefine command {name}");
+        }
+
+        let existing = self.aliases.iter().position(|(n, _)| n == name);
+        match (existing, expansion.trim().is_empty()) {
+            (Some(idx), true) => {
+                self.aliases.remove(idx);
+                println!("alias {name} removed");
+            }
+            (None, true) => println!("no such alias: {name}"),
+            (Some(idx), false) => self.aliases[idx].1 = expansion.to_string(),
+            (None, false) => self.aliases.push((name.to_string(), expansion.to_string())),
+        }
+    }
+
+    fn list_aliases(&self) {
+        if self.aliases.is_empty() {
+            return println!("no aliases defined");
+        }
+
+        for (name, expansion) in &self.aliases {
+            println!("{name} = {expansion}");
+        }
+    }
+
+    /// Parse and execute command line, expanding alias.
+    /// Commands of alias are executed until first that can't be parsed
+    fn run_line(&mut self, dbg: &mut dbg::SqDebugger, line: &str, save: bool) -> Result<()> {
+        let mut words = line.split_ascii_whitespace();
+        let alias = words.next().and_then(|w| self.aliases.iter().find(|(name, _)| name == w));
+        let Some((_, expansion)) = alias else {
+            let args = self.parse_args(line)?;
+            self.do_actions(dbg, args, save);
+            return Ok(());
+        };
+
+        let mut cmds: Vec<String> = expansion.split(';')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        // Arguments after alias go to its last command
+        if let Some(last) = cmds.last_mut() {
+            for word in words {
+                last.push(' ');
+                last.push_str(word);
+            }
+        }
+
+        for cmd in cmds {
+            let args = self.parse_args(&cmd)?;
+            self.do_actions(dbg, args, save);
+        }
+        Ok(())
+    }
+
+    /// Add, replace or remove (if `expr` is empty) virtual local
+    fn define_vlocal(&mut self, name: &str, expr: &str) {
+        // Allow both `name = expr` and `name=expr`
+        let (name, expr) = match name.split_once('=') {
+            Some((name, rest)) => (name, format!("{rest} {expr}")),
+            None => (name, expr.to_string()),
+        };
+        let expr = expr.trim().trim_start_matches('=').trim();
+        let existing = self.vlocals.iter().position(|(n, _)| n == name);
+
+        match (existing, expr.is_empty()) {
+            (Some(idx), true) => {
+                self.vlocals.remove(idx);
+                println!("virtual local {name} removed");
+            }
+            (None, true) => println!("no such virtual local: {name}"),
+            (Some(idx), false) => self.vlocals[idx].1 = expr.to_string(),
+            (None, false) => self.vlocals.push((name.to_string(), expr.to_string())),
+        }
+    }
+
+    fn list_vlocals(&self) {
+        if self.vlocals.is_empty() {
+            return println!("no virtual locals defined");
+        }
+
+        for (name, expr) in &self.vlocals {
+            println!("{name} = {expr}");
+        }
+    }
+
+    /// Evaluate virtual locals in current function and format them like locals
+    fn format_vlocals(&self, dbg: &dbg::SqDebugger) -> String {
+        let mut out = String::new();
+        if self.vlocals.is_empty() || self.during_eval {
+            return out;
+        }
+
+        let capture = Self::frame_capture(dbg);
+        writeln!(out, "Virtual locals:").unwrap();
+        for (name, expr) in &self.vlocals {
+            match dbg.execute(Self::wrap_expression(expr), capture.clone(), 0) {
+                Ok(val) => {
+                    write!(out, "    {name} (virtual): {:?}", val.get_type()).unwrap();
+                    match val {
+                        DynSqVar::Integer(_)
+                        | DynSqVar::Float(_)
+                        | DynSqVar::Bool(_)
+                        | DynSqVar::String(_) => writeln!(out, " = {val}"),
+                        _ => writeln!(out),
+                    }.unwrap();
+                }
+                Err(_) => writeln!(out, "    {name} (virtual): <err>").unwrap(),
+            }
+        }
+        out
+    }
+
+    /// Convert local path to squirrel expression, valid in eval of current function
+    fn path_to_expression(path: &str) -> Option<String> {
+        use SqPathToken::*;
+        let mut out = String::new();
+        for token in SqPathToken::lexer(path) {
+            match token {
+                Seg("this") if out.is_empty() => out.push_str("this_1"),
+                Seg(s) if out.is_empty() => out.push_str(s),
+                Seg(s) => write!(out, ".{s}").unwrap(),
+                QuotedSeg(_) if !out.is_empty() => {
+                    let key = token.text().unwrap().replace('\\', "\\\\").replace('"', "\\\"");
+                    write!(out, "[\"{key}\"]").unwrap()
+                }
+                Number(n) if !out.is_empty() => write!(out, "[{n}]").unwrap(),
+                Dot => (),
+                _ => return None,
+            }
+        }
+        (!out.is_empty()).then_some(out)
+    }
+
+    /// Evaluate expression for each element of container at path and print results
+    fn map_container(&self, dbg: &dbg::SqDebugger, path: &str, expr: &str, depth: usize) {
+        if self.during_eval {
+            return println!("failed to evaluate: cannot evaluate during evaluation");
+        }
+
+        let Some(container) = Self::path_to_expression(path) else {
+            return println!("path is invalid");
+        };
+
+        // Errors are caught by vm, so one element does not abort whole map
+        let script = format!(
+            "local out = [];
+            foreach (key, it in {container}) {{
+                try {{ out.append([key, ({expr})]); }}
+                catch (e) {{ out.append([key, null, e]); }}
+            }}
+            return out;"
+        );
+
+        // Additional levels for wrapping arrays
+        let results = match dbg.execute(script, Self::frame_capture(dbg), depth + 2) {
+            Ok(DynSqVar::Array(v)) => v,
+            Ok(res) => return println!("failed to evaluate: unexpected result {res}"),
+            Err(e) => return println!("failed to evaluate: {e}"),
+        };
+
+        if results.is_empty() {
+            return println!("container is empty");
+        }
+
+        for res in results {
+            match res {
+                DynSqVar::Array(v) if v.len() == 3 => println!("{}: <error: {}>", v[0], v[2]),
+                DynSqVar::Array(v) if v.len() == 2 => println!("{}: {}", v[0], v[1]),
+                other => println!("unexpected element result {other}"),
+            }
+        }
+    }
+
+    /// Get value of variable at path or table of current function locals
+    fn snapshot_value(&self, dbg: &dbg::SqDebugger, target: Option<&str>, depth: usize) -> Option<DynSqVar> {
+        if let Some(target) = target {
+            let Some((level, path)) = Self::split_local_path(target, self.current_frame().unwrap_or(1)) else {
+                println!("path is invalid");
+                return None;
+            };
+            return match dbg.get_local_child(Some(level), path, depth, false) {
+                Ok(val) => Some(val),
+                Err(e) => {
+                    println!("failed to match path `{target}`: {e}");
+                    None
+                }
+            };
+        }
+
+        let locals = match dbg.get_locals(Some(1), depth) {
+            Ok(locals) => locals,
+            Err(e) => {
+                println!("failed to get locals: {e}");
+                return None;
+            }
+        };
+
+        Some(Self::locals_table(locals))
+    }
+
+    /// Snapshot of locals, with same names as in eval captures
+    fn locals_table(locals: impl IntoIterator<Item = SqLocalVarWithLvl>) -> DynSqVar {
+        let table = locals.into_iter()
+            .map(|SqLocalVarWithLvl { var: SqLocalVar { name, val }, lvl }| {
+                let name = if name == "this" { format!("this_{lvl}") } else { name };
+                (DynSqVar::String(name), val)
+            })
+            .collect();
+        DynSqVar::Table(table)
+    }
+
+    /// Save current function locals or variable at path to named snapshot
+    fn take_snapshot(&mut self, dbg: &dbg::SqDebugger, name: &str, target: Option<&str>, depth: usize) {
+        let Some(snap) = self.snapshot_value(dbg, target, depth) else {
+            return;
+        };
+
+        self.snapshots.insert(name.to_string(), snap);
+        println!("snapshot {name} saved");
+    }
+
+    /// Print differences between snapshot and current value
+    fn diff_snapshot(&self, dbg: &dbg::SqDebugger, name: &str, target: Option<&str>, depth: usize) {
+        let Some(snap) = self.snapshots.get(name) else {
+            return println!("no such snapshot: {name}");
+        };
+        let Some(current) = self.snapshot_value(dbg, target, depth) else {
             return;
         };
 
-        self.during_eval = true;
+        let mut changes = vec![];
+        Self::diff_values(snap, &current, target.unwrap_or(""), &mut changes);
+        if changes.is_empty() {
+            return println!("no changes");
+        }
+
+        for change in changes {
+            println!("{change}");
+        }
+    }
+
+    /// Recursively collect added `+`, removed `-` and changed `~` values at paths
+    fn diff_values(old: &DynSqVar, new: &DynSqVar, path: &str, changes: &mut Vec<String>) {
+        // Containers are summarized by type, their changes are listed separately
+        let repr = |val: &DynSqVar| if val.get_type().is_complex() {
+            format!("{:?}", val.get_type())
+        } else {
+            val.to_string()
+        };
+        let child_path = |key: &DynSqVar| child_path(path, key);
+
+        match (old, new) {
+            (DynSqVar::Table(old_map), DynSqVar::Table(new_map))
+            | (DynSqVar::Class(old_map), DynSqVar::Class(new_map))
+            | (DynSqVar::Instance(SqInstance { this: old_map }), DynSqVar::Instance(SqInstance { this: new_map })) => {
+                for (key, old_val) in old_map {
+                    match new_map.get(key) {
+                        Some(new_val) => Self::diff_values(old_val, new_val, &child_path(key), changes),
+                        None => changes.push(format!("- {}: {}", child_path(key), repr(old_val))),
+                    }
+                }
+                for (key, new_val) in new_map.iter().filter(|(key, _)| !old_map.contains_key(*key)) {
+                    changes.push(format!("+ {}: {}", child_path(key), repr(new_val)));
+                }
+            }
+            (DynSqVar::Array(old_v), DynSqVar::Array(new_v)) => {
+                for (idx, (old_val, new_val)) in old_v.iter().zip(new_v).enumerate() {
+                    Self::diff_values(old_val, new_val, &child_path(&DynSqVar::Integer(idx as isize)), changes);
+                }
+                for (idx, old_val) in old_v.iter().enumerate().skip(new_v.len()) {
+                    changes.push(format!("- {}: {}", child_path(&DynSqVar::Integer(idx as isize)), repr(old_val)));
+                }
+                for (idx, new_val) in new_v.iter().enumerate().skip(old_v.len()) {
+                    changes.push(format!("+ {}: {}", child_path(&DynSqVar::Integer(idx as isize)), repr(new_val)));
+                }
+            }
+            (old, new) if !old.deep_eq(new) => {
+                let path = if path.is_empty() { "value" } else { path };
+                changes.push(format!("~ {path}: {} -> {}", repr(old), repr(new)));
+            }
+            _ => (),
+        }
+    }
+
+    fn list_snapshots(&self) {
+        if self.snapshots.is_empty() {
+            return println!("no snapshots saved");
+        }
 
-        let eval_res = |res| match res {
-            Ok(res) => println!("evaluation result: {res}"),
-            Err(e) => println!("failed to evaluate: {e}"),  
+        for (name, snap) in &self.snapshots {
+            let keys: Vec<_> = match snap {
+                DynSqVar::Table(t) => t.keys().map(|k| k.to_string()).collect(),
+                other => vec![format!("{:?}", other.get_type())],
+            };
+            println!("{name}: {}", keys.join(", "));
+        }
+    }
+
+    /// Convert snapshot to values that can be pushed into vm, warning about lost data
+    fn snapshot_values(snap: &DynSqVar) -> IndexMap<String, DynSqVar> {
+        let DynSqVar::Table(table) = snap else {
+            return IndexMap::new();
         };
 
-        if !debug {
-            eval_res(dbg.execute(script, capture, depth))
+        let mut lossy = vec![];
+        let values: IndexMap<_, _> = table.iter()
+            .map(|(k, v)| {
+                let name = match k {
+                    DynSqVar::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                let val = Self::copyable(v, &name, &mut lossy);
+                (name, val)
+            })
+            .collect();
+
+        if values.values().any(|v| v.get_type().is_complex()) {
+            println!("warning: snapshot containers are copies, changes won't affect live objects");
         }
-        else { 
-            let fut = dbg.execute_debug(script, capture, depth);
-            
-            // Spawn thread that will wait for eval result
-            std::thread::spawn(move || eval_res(fut()));
+        for path in lossy {
+            println!("warning: {path}");
         }
 
+        values
+    }
 
-        self.during_eval = false;
+    /// Replace values, that can't be pushed into vm, with tables or nulls
+    fn copyable(val: &DynSqVar, path: &str, lossy: &mut Vec<String>) -> DynSqVar {
+        match val {
+            DynSqVar::Null
+            | DynSqVar::Integer(_)
+            | DynSqVar::Float(_)
+            | DynSqVar::Bool(_)
+            | DynSqVar::String(_)
+            | DynSqVar::UserData(_) => val.clone(),
+
+            DynSqVar::Array(v) => DynSqVar::Array(v.iter()
+                .enumerate()
+                .map(|(i, v)| Self::copyable(v, &format!("{path}[{i}]"), lossy))
+                .collect()
+            ),
+
+            DynSqVar::Table(map)
+            | DynSqVar::Class(map)
+            | DynSqVar::Instance(SqInstance { this: map }) => {
+                if !matches!(val, DynSqVar::Table(_)) {
+                    lossy.push(format!("{path}: {:?} passed as table", val.get_type()));
+                }
+                DynSqVar::Table(map.iter()
+                    .map(|(k, v)| (
+                        Self::copyable(k, &format!("{path} key"), lossy),
+                        Self::copyable(v, &format!("{path}.{k}"), lossy),
+                    ))
+                    .collect()
+                )
+            }
+
+            other => {
+                lossy.push(format!("{path}: {:?} passed as null", other.get_type()));
+                DynSqVar::Null
+            }
+        }
     }
 
     /// Process buffer commands
     fn manipulate_buffer(&mut self, cmd: BufferCommands) {
         match cmd {
-            BufferCommands::New => match Self::edit_buffer(None) {
-                Ok(s) =>println!("new buffer number: {}", self.buffers.add(s)),
+            BufferCommands::New => match self.edit_buffer(None) {
+                Ok(s) => if let Some(num) = self.new_buffer(s) {
+                    println!("new buffer number: {num}")
+                },
                 Err(e) => println!("failed to open editor: {e}"),
             }
 
-            BufferCommands::Delete { num } => self.buffers.delete(num),
-            BufferCommands::Edit { num } => 
-            if let Some(b) = self.buffers.get(num) {
-                match Self::edit_buffer(Some(b)) {
+            BufferCommands::Delete { buf } => match self.buffers.resolve(&buf) {
+                Some(num) => self.buffers.delete(num),
+                None => println!("no such buffer"),
+            }
+            BufferCommands::Edit { buf } => 
+            if let Some(num) = self.buffers.resolve(&buf) {
+                let b = self.buffers.get(num).unwrap().to_string();
+                match self.edit_buffer(Some(&b)) {
                     Ok(s) => self.buffers.replace(num, s),
                     Err(e) => println!("failed to open editor: {e}"),
                 }
@@ -582,14 +3280,40 @@ impl DebuggerFrontend {
                 println!("no such buffer")
             }
         
-            BufferCommands::Print { num } => 
-            if let Some(b) = self.buffers.get(num) {
+            BufferCommands::Print { buf } => 
+            if let Some(b) = self.buffers.resolve(&buf).and_then(|num| self.buffers.get(num)) {
                 Self::print_augmented_code_chunk(b, 1, None, None);
             } else {
                 println!("no such buffer")
             }
             
             BufferCommands::List => self.buffers.list_items(),
+            BufferCommands::Name { buf, name } => match self.buffers.resolve(&buf) {
+                Some(num) => if let Err(e) = self.buffers.set_name(num, name) {
+                    println!("failed to name buffer: {e}");
+                }
+                None => println!("no such buffer"),
+            }
+            BufferCommands::Move { buf, num } => match self.buffers.resolve(&buf) {
+                Some(old) => if let Err(e) = self.buffers.renumber(old, num) {
+                    println!("failed to move buffer: {e}");
+                }
+                None => println!("no such buffer"),
+            }
+            BufferCommands::Save { buf, path } => 
+            if let Some(b) = self.buffers.resolve(&buf).and_then(|num| self.buffers.get(num)) {
+                if let Err(e) = std::fs::write(&path, b) {
+                    println!("failed to save buffer: {e}");
+                }
+            } else {
+                println!("no such buffer")
+            }
+            BufferCommands::Load { path } => match std::fs::read_to_string(&path) {
+                Ok(s) => if let Some(num) = self.new_buffer(s) {
+                    println!("new buffer number: {num}")
+                },
+                Err(e) => println!("failed to load buffer: {e}"),
+            }
         }
     }
 
@@ -679,20 +3403,264 @@ impl DebuggerFrontend {
         }
     }
 
-    /// Save state to file
+    /// Print whole function source by call stack level or path
+    fn print_function_source(&self, dbg: &dbg::SqDebugger, target: Option<&str>) {
+        if self.srcs.dirs().is_empty() {
+            return println!("no source directories registered, see `help src`");
+        }
+
+        let level = match target {
+            None => Some(self.current_frame().unwrap_or(1)),
+            Some(t) => t.parse::<usize>().ok(),
+        };
+
+        match self.find_function_proto(dbg, target) {
+            Ok(proto) => if let (Some(file), Some((first, last))) = (&proto.src, proto.line_range()) {
+                let first = first.max(1) as usize;
+                if let Some(chunk) = self.srcs.lines(file, first, last.max(0) as usize) {
+                    let cursor = level
+                        .and_then(|level| dbg.get_backtrace().ok()?.into_iter().nth(level.saturating_sub(1)))
+                        .and_then(|frame| frame.line)
+                        .map(|line| line as usize);
+                    let spec = BrkSpec { file: Some(file.clone()), func: proto.name, line: Some(first) };
+                    println!("{spec}:");
+                    return Self::print_augmented_code_chunk(chunk, first, cursor, None);
+                }
+            },
+            Err(e) => println!("no line info ({e}), scanning sources"),
+        }
+
+        // Fallback for functions not reachable from root table
+        let (spec, cursor) = match (level, target) {
+            (Some(level), _) => {
+                let frame = match dbg.get_backtrace() {
+                    Ok(bt) => match bt.into_iter().nth(level.saturating_sub(1)) {
+                        Some(frame) => frame,
+                        None => return println!("no function at level {level}"),
+                    },
+                    Err(e) => return println!("failed to get backtrace: {e}"),
+                };
+
+                let (Some(file), Some(func)) = (frame.src_file, frame.funcname) else {
+                    return println!("source file or name of function at level {level} is unknown");
+                };
+
+                let line = frame.line.map(|l| l as usize);
+                // Current line selects function if names are ambiguous in file
+                (BrkSpec { file: Some(file), func: Some(func), line }, line)
+            }
+            (None, Some(path)) => match BrkSpec::parse(path) {
+                Ok(spec) => (spec, None),
+                Err(_) => return println!("failed to parse path"),
+            },
+            (None, None) => unreachable!(),
+        };
+
+        self.find_sources(&spec, None, cursor);
+    }
+
+    /// Save state to file in format inferred from its extension
     fn save(state: SavedState, path: &str) -> Result<()> {
+        match StateFormat::from_path(path) {
+            StateFormat::Json => serde_json::to_writer_pretty(&File::create(path)?, &state)?,
+            StateFormat::Toml => std::fs::write(path, toml::to_string_pretty(&state)?)?,
+            StateFormat::Yaml => serde_yaml::to_writer(&File::create(path)?, &state)?,
+        }
+        Ok(())
+    }
+
+    /// Save locals at call stack level to file
+    fn save_frame(dbg: &dbg::SqDebugger, level: usize, depth: usize, path: &str) -> Result<()> {
+        let locals = dbg.get_locals(Some(level), depth)?
+            .into_iter()
+            .map(|SqLocalVarWithLvl { var: SqLocalVar { name, val }, .. }| {
+                SavedLocal { name, value: val }
+            })
+            .collect();
+
+        let f = File::create(path)?;
+        serde_json::to_writer_pretty(&f, &SavedFrame { level, locals })?;
+        Ok(())
+    }
+
+    /// Write scalars saved by `stack-save` back to containers of locals at call stack level
+    fn restore_frame(dbg: &dbg::SqDebugger, level: usize, path: &str, yes: bool) -> Result<()> {
+        /// Collect paths of scalars in saved value
+        fn collect(value: &serde_json::Value, path: Vec<SqPathKey>, out: &mut Vec<(Vec<SqPathKey>, DynSqVar)>) {
+            use serde_json::Value;
+            let child = |key| path.iter().cloned().chain(std::iter::once(key)).collect();
+            let scalar = match value {
+                Value::Object(map) => return map.iter()
+                    .for_each(|(k, v)| collect(v, child(SqPathKey::Name(k.clone())), out)),
+                Value::Array(v) => return v.iter()
+                    .enumerate()
+                    .for_each(|(idx, v)| collect(v, child(SqPathKey::Index(idx as isize)), out)),
+                Value::Null => DynSqVar::Null,
+                Value::Bool(b) => DynSqVar::Bool(*b),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => DynSqVar::Integer(i as isize),
+                    None => DynSqVar::Float(n.as_f64().unwrap_or_default() as SqFloat),
+                },
+                Value::String(s) => DynSqVar::String(s.clone()),
+            };
+            out.push((path, scalar));
+        }
+
+        let frame: LoadedFrame = serde_json::from_reader(File::open(path)?)?;
+        let mut values = vec![];
+        for LoadedLocal { name, value } in frame.locals {
+            collect(&value, vec![SqPathKey::Name(name)], &mut values);
+        }
+
+        // Locals themselves can't be set
+        let (values, locals): (Vec<_>, Vec<_>) = values.into_iter().partition(|(path, _)| path.len() > 1);
+        for (path, _) in &locals {
+            println!("skipped {}: local can't be set", format_path(path));
+        }
+        if values.is_empty() {
+            bail!("no members of containers to restore");
+        }
+
+        if !yes {
+            print!("overwrite {} values in locals at level {level}? this is unsafe [y/N] ", values.len());
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            if !matches!(input.trim().to_lowercase().as_str(), "y" | "yes") {
+                println!("restore cancelled");
+                return Ok(());
+            }
+        }
+
+        let total = values.len();
+        let mut restored = 0;
+        for (path, value) in values {
+            // Integer keys of tables were saved as strings
+            let int_path: Vec<_> = path.iter()
+                .map(|key| match key {
+                    SqPathKey::Name(name) => name.parse().map_or_else(|_| key.clone(), SqPathKey::Index),
+                    key => key.clone(),
+                })
+                .collect();
+
+            let res = dbg.set_local(level, path.clone(), value.clone())
+                .or_else(|e| match int_path != path {
+                    true => dbg.set_local(level, int_path, value),
+                    false => Err(e),
+                });
+            match res {
+                Ok(()) => restored += 1,
+                Err(e) => println!("skipped {}: {e}", format_path(&path)),
+            }
+        }
+
+        println!("restored {restored} of {total} values");
+        Ok(())
+    }
+
+    /// Save value of local or global variable at path to file
+    fn dump_value(&self, dbg: &dbg::SqDebugger, target: &str, depth: usize, path: &str) -> Result<()> {
+        let value = if let Some(global) = target.strip_prefix("::") {
+            let Some(keys) = Self::split_path(global) else {
+                bail!("path is invalid");
+            };
+            dbg.get_global_child(keys, depth)?
+        } else {
+            let Some((level, keys)) = Self::split_local_path(target, self.current_frame().unwrap_or(1)) else {
+                bail!("path is invalid");
+            };
+            dbg.get_local_child(Some(level), keys, depth, false)?
+        };
+
         let f = File::create(path)?;
-        serde_json::to_writer_pretty(&f, &state)?;
+        serde_json::to_writer_pretty(&f, &value)?;
         Ok(())
     }
 
-    /// Load state from file
+    /// Load state from file in format inferred from its extension
     fn load(path: &str) -> Result<SavedState> {
-        let f = File::open(path)?;
-        let state: SavedState = serde_json::from_reader(f)?;
+        let state = match StateFormat::from_path(path) {
+            StateFormat::Json => serde_json::from_reader(File::open(path)?)?,
+            StateFormat::Toml => toml::from_str(&std::fs::read_to_string(path)?)?,
+            StateFormat::Yaml => serde_yaml::from_reader(File::open(path)?)?,
+        };
         Ok(state)
     }
 
+    /// Find config file in working directory, then in home directory
+    fn find_config() -> Option<std::path::PathBuf> {
+        let home = std::env::var_os("USERPROFILE").or_else(|| std::env::var_os("HOME"));
+
+        std::iter::once(Some(std::path::PathBuf::from(CONFIG_FILENAME)))
+            .chain(std::iter::once(home.map(|h| Path::new(&h).join(CONFIG_FILENAME))))
+            .flatten()
+            .find(|p| p.is_file())
+    }
+
+    /// Execute commands from config file
+    fn run_config(&mut self, dbg: &mut dbg::SqDebugger) {
+        let Some(path) = Self::find_config() else {
+            return;
+        };
+
+        if self.run_batch(dbg, &path) {
+            println!("Config {} applied", path.display());
+        }
+    }
+
+    /// Execute commands from file, one per line.
+    /// Empty lines and lines starting with `#` are skipped.
+    ///
+    /// Returns `false` if file was not read or execution was aborted.
+    /// File, that is already running, e.g. runs itself through alias, is not executed again
+    fn run_batch(&mut self, dbg: &mut dbg::SqDebugger, path: &Path) -> bool {
+        let batch = match std::fs::read_to_string(path) {
+            Ok(b) => b,
+            Err(e) => {
+                println!("failed to read {}: {e}", path.display());
+                return false;
+            }
+        };
+
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.running_batches.contains(&canonical) {
+            println!("failed to run {}: file is already running", path.display());
+            return false;
+        }
+
+        self.running_batches.push(canonical);
+        let done = self.run_batch_lines(dbg, path, &batch);
+        self.running_batches.pop();
+        done
+    }
+
+    /// Execute lines of batch file at `path`
+    fn run_batch_lines(&mut self, dbg: &mut dbg::SqDebugger, path: &Path, batch: &str) -> bool {
+        // Failure of command outside of batch must not abort it
+        self.cmd_failed = false;
+        for (n, line) in batch.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let failed = match self.run_line(dbg, line, false) {
+                Ok(()) => std::mem::take(&mut self.cmd_failed),
+                Err(e) => {
+                    println!("{}:{}: {e}", path.display(), n + 1);
+                    self.stop_on_error
+                }
+            };
+
+            if failed {
+                println!("{}:{}: command failed, rest of file skipped", path.display(), n + 1);
+                return false;
+            }
+        }
+
+        true
+    }
+
     /// Repeat last executed args
     fn repeat_last_cmd(&mut self, dbg: &mut dbg::SqDebugger) {
         if let Some(cmd) = self.last_cmd.take() {
@@ -702,43 +3670,249 @@ impl DebuggerFrontend {
 
     /// Execute parsed args. Save to internal buffer, if owned
     fn do_actions(&mut self, dbg: &mut dbg::SqDebugger, args: Commands, save: bool) {
-        match &args {
-            Commands::Step => dbg.step(),
-            Commands::Continue => dbg.resume(),
+        // Snapshots of logpoints arrive while running
+        self.save_hit_snapshots(dbg);
 
-            Commands::Backtrace => match dbg.get_backtrace() {
-                Ok(bt) => Self::print_backtrace(bt),
+        match &args {
+            Commands::Step { while_not: Some(expr), max, .. } => self.step_while_not(dbg, expr, *max),
+            Commands::Step { count: Some(count), .. } if *count > 1 => self.step_count(dbg, *count),
+            Commands::Step { .. } => dbg.step(),
+            // Finest granularity available
+            Commands::StepInstruction => dbg.step(),
+            Commands::Istep { path } => {
+                let Some(input) = self.interactive_step(dbg, path) else { return };
+
+                // Exiting command is executed instead of this one
+                match self.parse_args(&input) {
+                    Ok(args) => self.do_actions(dbg, args, save),
+                    Err(e) => println!("{e}"),
+                }
+                return;
+            }
+            Commands::Next => dbg.step_over(),
+            Commands::Finish => dbg.step_out(),
+            Commands::Continue => {
+                // `hits` ranks breakpoints of the last run only
+                dbg.breakpoints().reset_hits();
+                dbg.resume();
+            }
+            Commands::Until { spec } => self.run_until(dbg, spec),
+
+            Commands::Backtrace { timeout, clip } => 
+            match Self::with_timeout(dbg, *timeout, || dbg.get_backtrace()) {
+                Ok(bt) if self.output_format == OutputFormat::Json => Self::print_json(&bt),
+                Ok(bt) => {
+                    Self::print_backtrace(&bt);
+                    if *clip {
+                        Self::copy_to_clipboard(format!("Backtrace:\n{}", Self::format_frames(&bt)));
+                    }
+                }
                 Err(e) => println!("failed to get backtrace: {e}"),
             }
 
-            Commands::Locals { level } =>
-            match dbg.get_locals(*level, 0) {
-                Ok(locals) => locals.list_items(),
-                Err(e) => println!("failed to get locals: {e}"),
+            Commands::Frame { num } => self.select_frame(dbg, *num),
+            Commands::Up => self.select_frame(dbg, Some(self.current_frame().unwrap_or(1) + 1)),
+            Commands::Down => match self.current_frame() {
+                Some(lvl) if lvl > 1 => self.select_frame(dbg, Some(lvl - 1)),
+                _ => println!("already at innermost frame"),
+            }
+
+            Commands::Locals { level, timeout, clip } => {
+                let level = level.or(self.current_frame());
+                match Self::with_timeout(dbg, *timeout, || dbg.get_locals(level, 0)) {
+                    Ok(locals) if self.output_format == OutputFormat::Json => Self::print_json(&locals),
+                    Ok(locals) => {
+                        locals.list_items();
+                        // Virtual locals are computed only in current function
+                        let virt = match level {
+                            None | Some(1) => self.format_vlocals(dbg),
+                            Some(_) => String::new(),
+                        };
+                        print!("{virt}");
+                        if *clip {
+                            Self::copy_to_clipboard(format_locals(&locals) + &virt);
+                        }
+                    }
+                    Err(e) => println!("failed to get locals: {e}"),
+                }
             }
 
-            Commands::Examine { level, target, depth } 
-                => Self::examine(dbg, target, *level, *depth),
+            Commands::Examine { target, depth, only, as_source, into_closures, format, timeout, clip } => {
+                let (target, level) = match &target[..] {
+                    [path, lvl] if Self::is_local_path(path) && lvl.parse::<usize>().is_ok()
+                        => (path.clone(), lvl.parse().ok()),
+                    _ => (target.join(" "), None),
+                };
+                let level = level.or(self.current_frame());
+
+                let text = Self::with_timeout(dbg, *timeout, || if Self::is_local_path(&target) {
+                    let json = self.output_format == OutputFormat::Json;
+                    Self::examine(dbg, &target, level, *depth, only, *as_source, *into_closures, json, (*format).into())
+                } else {
+                    let lvl = level.unwrap_or(1);
+                    self.print_expression(dbg, &Self::rename_this(&target, lvl), lvl, *depth, (*format).into())
+                });
+                if let (true, Some(text)) = (*clip, text) {
+                    Self::copy_to_clipboard(text);
+                }
+            }
                 
-            Commands::BreakpointAdd { spec } => Self::add_breakpoint(dbg, spec),
+            Commands::Where => self.print_where(dbg),
+            Commands::Map { target, expr, depth } => self.map_container(dbg, target, &expr.join(" "), *depth),
+            Commands::Queue => Self::print_queue(dbg),
+            Commands::BreakDepth { depth: None } => match dbg.halt_depth() {
+                Some(depth) => println!("will halt at call stack depth {depth}"),
+                None => println!("no depth trigger set"),
+            }
+            Commands::BreakDepth { depth: Some(0) } => {
+                dbg.set_halt_depth(None);
+                self.armed_depth = None;
+            }
+            Commands::BreakDepth { depth: Some(depth) } => {
+                dbg.set_halt_depth(Some(*depth));
+                self.armed_depth = Some(*depth);
+            }
+            Commands::Reattach => self.reattach(dbg),
+            Commands::Detach => self.detach(dbg),
+            Commands::Alias { name, expansion } => self.define_alias(name, &expansion.join(" ")),
+            Commands::AliasList => self.list_aliases(),
+            Commands::Vlocal { name: None, .. } => self.list_vlocals(),
+            Commands::Vlocal { name: Some(name), expr } => self.define_vlocal(name, &expr.join(" ")),
+            Commands::SourceFn { target } => self.print_function_source(dbg, target.as_deref()),
+            Commands::LineInfo { target } => self.print_line_info(dbg, target.as_deref()),
+            Commands::Signature { level } => Self::print_signature(dbg, level.unwrap_or(1)),
+            Commands::InfoFrame { level } => Self::print_frame_info(dbg, level.or(self.current_frame()).unwrap_or(1)),
+            Commands::Defaults { target } => self.print_defaults(dbg, target.as_deref()),
+            Commands::Raw { target, level } => Self::print_raw(dbg, target, *level),
+            Commands::SetLocal { target, value } => Self::set_local(dbg, target, &value.join(" ")),
+            Commands::Globals { depth } => self.print_globals(dbg, *depth),
+            Commands::Find { value, global, depth } 
+                => self.find(dbg, &value.join(" "), *global, *depth),
+            Commands::Print { expr, depth } => {
+                self.print_expression(dbg, &expr.join(" "), 1, *depth, SqIntFormat::Dec);
+            }
+            Commands::Expect { expr, depth } => self.expect(dbg, &expr.join(" "), *depth),
+            Commands::Overhead { secs } => Self::measure_overhead(dbg, *secs),
+            Commands::Uptime { reset: true } => dbg.reset_uptime(),
+            Commands::Uptime { reset: false } => {
+                let (elapsed, events) = dbg.uptime();
+                println!("uptime: {elapsed:.1?}, debug events: {events}");
+            }
+            Commands::CatchErrors { active } => dbg.set_catch_errors((*active).into()),
+            Commands::Profile { active } => dbg.profiler().counting = (*active).into(),
+            Commands::ProfileReport { count } => Self::print_call_counts(dbg, *count),
+            Commands::ProfileTime { active } => dbg.profiler().set_timing((*active).into()),
+            Commands::ProfileTimeReport { count } => Self::print_function_times(dbg, *count),
+            Commands::ProfileFlame { path } => {
+                let folded = dbg.profiler().folded_stacks();
+                if folded.is_empty() {
+                    println!("no call stacks measured, see `help profile-time`");
+                } else if let Err(e) = std::fs::write(path, folded) {
+                    println!("failed to write stacks: {e}");
+                }
+            }
+            Commands::Coverage { active } => dbg.profiler().covering = (*active).into(),
+            Commands::CoverageReport { path } => Self::write_coverage(dbg, path),
+            Commands::ProfileReset => dbg.profiler().reset(),
+            Commands::Errors { clear: true, .. } => dbg.errors().clear(),
+            Commands::Errors { count, .. } => Self::print_errors(dbg, *count),
+            Commands::BreakpointAdd { spec, condition, snapshot } 
+                => match Self::add_breakpoint(dbg, spec, condition, *snapshot, false) {
+                Ok(()) => (),
+                Err(e) if self.strict_specs => {
+                    println!("error: {e}");
+                    self.cmd_failed = true;
+                }
+                Err(e) => println!("{e}"),
+            }
+            Commands::BreakpointTemp { spec, condition } 
+                => match Self::add_breakpoint(dbg, spec, condition, false, true) {
+                Ok(()) => (),
+                Err(e) if self.strict_specs => {
+                    println!("error: {e}");
+                    self.cmd_failed = true;
+                }
+                Err(e) => println!("{e}"),
+            }
+            Commands::BreakHere => self.break_here(dbg),
+            Commands::Watch { expr } => println!("watch {} added", self.watches.add(None, expr.join(" "))),
+            Commands::WatchAt { spec, expr } => match BrkSpec::parse(spec) {
+                Ok(loc) => println!("watch {} added", self.watches.add(Some(loc), expr.join(" "))),
+                Err(_) => println!("failed to parse specification"),
+            }
+            Commands::Unwatch { num } => self.watches.delete(*num),
+            Commands::WatchList => self.watches.list_items(),
+            Commands::WatchVar { target: None } => dbg.watchpoints().list_items(),
+            Commands::WatchVar { target: Some(target) } => {
+                let level = self.current_frame().unwrap_or(1);
+                let Some((level, path)) = Self::split_local_path(target, level) else {
+                    return println!("path is invalid");
+                };
+                match dbg.add_watchpoint(level, path) {
+                    Ok(num) => println!("watchpoint {num} added"),
+                    Err(e) => println!("failed to add watchpoint: {e}"),
+                }
+            }
+            Commands::UnwatchVar { num } => dbg.watchpoints().remove(*num),
+            Commands::BreakCallers { func } => self.break_callers(dbg, func),
             Commands::BreakpointEnable { num } => dbg.breakpoints().enable(*num, true),
             Commands::BreakpointDisable { num } => dbg.breakpoints().enable(*num, false),
             Commands::BreakpointClear { num } => dbg.breakpoints().remove(*num),
+            Commands::BreakpointIgnore { num, count } => if !dbg.breakpoints().set_ignore(*num, *count) {
+                println!("no such breakpoint");
+            }
             Commands::BreakpointList => dbg.breakpoints().list_items(),
+            Commands::Hits { reset: true } => dbg.breakpoints().reset_hits(),
+            Commands::Hits { reset: false } => Self::print_hits(dbg),
+            Commands::DisableNoisiest => Self::disable_noisiest(dbg),
+            Commands::BreakVerify => self.verify_breakpoints(dbg, false),
+
+            Commands::Evaluate { debug , buffer, depth, snapshot, capture_output }
+                => self.eval_script(dbg, *debug, buffer.as_deref(), *depth, snapshot.as_deref(), *capture_output),
+
+            Commands::Source { path, debug, depth } => self.source_file(dbg, path, *debug, *depth),
+            Commands::Snapshot { name: Some(name), target, depth } 
+                => self.take_snapshot(dbg, name, target.as_deref(), *depth),
+            Commands::Diff { name, target, depth } => self.diff_snapshot(dbg, name, target.as_deref(), *depth),
+            Commands::Snapshot { name: None, .. } => self.list_snapshots(),
+
+            Commands::Buffer(cmd) => self.manipulate_buffer(cmd.clone()),
+            Commands::Trace { src, only, out, format, args, dedup } => {
+                if let Some(path) = out {
+                    *self.trace_rec.lock().unwrap() = Some(TraceRecorder::new(path.clone(), *format));
+                }
+                self.trace_dedup.store(*dedup, atomic::Ordering::Relaxed);
 
-            Commands::Evaluate { debug , buffer, depth }
-                => self.eval_script(dbg, *debug, *buffer, *depth),
-
-            Commands::Buffer(cmd) => self.manipulate_buffer(*cmd),
-            Commands::Trace => dbg.start_tracing(),
+                let filter: Option<dbg::TraceFilter> = if src.is_none() && only.is_empty() {
+                    None
+                } else {
+                    let (src, only) = (src.clone(), only.clone());
+                    Some(Box::new(move |e: &DebugEventWithSrc| {
+                        let src_match = match (&src, &e.src) {
+                            (Some(filter), Some(src)) => src.contains(filter.as_str()),
+                            (Some(_), None) => false,
+                            (None, _) => true,
+                        };
+                        src_match && (only.is_empty() || only.contains(&TraceKind::of(&e.event)))
+                    }))
+                };
+                dbg.start_tracing(*args, filter)
+            }
+            Commands::LogSlice { start, end, file, func } 
+                => self.print_log_slice(*start, *end, file.as_deref(), func.as_deref()),
             Commands::Src(cmd) => self.manipulate_sources(cmd.clone()),
+            Commands::List { .. } if self.srcs.dirs().is_empty()
+                => println!("no source directories registered, see `help set source-dir`"),
+            Commands::List { radius } => self.manipulate_sources(SrcCommands::Show { window: radius * 2 + 1 }),
 
             Commands::Load { file } => 
             match Self::load(file.as_deref().unwrap_or(DEFAULT_STATE_FILENAME)) {
-                Ok(SavedState { buffers, breakpoints, src_dirs, display_cmd }) => {
+                Ok(SavedState { buffers, breakpoints, src_dirs, display_cmd, vlocals, aliases }) => {
                     self.buffers = buffers;
+                    self.vlocals = vlocals;
+                    self.aliases = aliases;
                     dbg.set_breakpoints(breakpoints);
-                    for (path, prefix) in src_dirs {
+                    for SavedSrcDir { path, prefix } in src_dirs {
                         self.manipulate_sources(SrcCommands::Add { path, prefix })
                     }
                     for cmd in display_cmd {
@@ -748,21 +3922,28 @@ impl DebuggerFrontend {
                         };
                         self.display_cmds.add(args, cmd);
                     }
+                    self.verify_breakpoints(dbg, true);
                 },
                 Err(e) => println!("Failed to load state: {e}"),
             },
 
+            Commands::Run { path } => if self.run_batch(dbg, Path::new(path)) {
+                println!("{path} done");
+            }
+
             Commands::Save { file } => {
                 let state = SavedState { 
                     buffers: self.buffers.clone(),
                     breakpoints: dbg.breakpoints().clone(),
                     src_dirs: self.srcs.dirs().iter()
-                        .map(|d| (d.path.clone(), d.prefix.clone()))
+                        .map(|d| SavedSrcDir { path: d.path.clone(), prefix: d.prefix.clone() })
                         .collect(),
                     display_cmd: self.display_cmds.store.iter()
                         .map(|(_, cmd, _)| cmd)
                         .cloned()
                         .collect(),
+                    vlocals: self.vlocals.clone(),
+                    aliases: self.aliases.clone(),
                 };
 
                 if let Err(e) = Self::save(state, file.as_deref().unwrap_or(DEFAULT_STATE_FILENAME)) {
@@ -770,8 +3951,23 @@ impl DebuggerFrontend {
                 }
             }
 
+            Commands::StackSave { level, file, depth } => 
+            if let Err(e) = Self::save_frame(dbg, *level, *depth, file) {
+                println!("failed to save frame: {e}")
+            }
+
+            Commands::StackRestore { level, file, yes } => 
+            if let Err(e) = Self::restore_frame(dbg, *level, file, *yes) {
+                println!("failed to restore frame: {e}")
+            }
+
+            Commands::Dump { target, path, depth } => 
+            if let Err(e) = self.dump_value(dbg, target, *depth, path) {
+                println!("failed to dump value: {e}")
+            }
+
             Commands::Display(cmd) => self.manipulate_display(cmd.clone()),
-            Commands::Set(var) => Self::set_var(var),
+            Commands::Set(var) => self.set_var(dbg, var),
             Commands::Exit => std::process::exit(0),
         };     
         if save {
@@ -868,6 +4064,99 @@ impl DebuggerFrontend {
         }
     }
 
+    /// Print traced call arguments in one line
+    fn print_call_args(args: &[SqLocalVar]) {
+        let args: Vec<_> = args.iter().map(|SqLocalVar { name, val }| {
+            let mut val = val.to_string().replace('\n', " ");
+            if val.chars().count() > TRACE_ARG_MAX_LEN {
+                val = val.chars().take(TRACE_ARG_MAX_LEN).collect::<String>() + "...";
+            }
+            format!("{name} = {val}")
+        }).collect();
+
+        println!("      args: {}", args.join(", "));
+    }
+
+    /// Print logged events in range, optionally filtered by file and function
+    fn print_log_slice(&self, start: isize, end: Option<isize>, file: Option<&str>, func: Option<&str>) {
+        let log = self.event_log.lock().unwrap();
+        let mut printed = 0;
+
+        for (idx, e) in log.slice(start, end) {
+            if let Some(file) = file {
+                if !e.src.as_deref().is_some_and(|src| src.contains(file)) {
+                    continue;
+                }
+            }
+
+            if let Some(func) = func {
+                match &e.event {
+                    DebugEvent::FnCall(name, _) | DebugEvent::FnRet(name, _) if name == func => (),
+                    _ => continue,
+                }
+            }
+
+            println!("{idx:>6}: {e}");
+            printed += 1;
+        }
+
+        if printed == 0 {
+            println!("no events in range, log contains events {}..{}", log.dropped, log.end());
+        }
+    }
+
+    /// Write recorded trace, if recording was active
+    fn finish_trace(&self, dbg: &dbg::SqDebugger) {
+        // Trace command may be not taken by hook yet, so vm is still halted before it
+        if dbg.queue_state().0 > 0 {
+            return;
+        }
+
+        let Some(rec) = self.trace_rec.lock().unwrap().take() else {
+            return;
+        };
+        let path = rec.path.clone();
+        if rec.is_empty() {
+            return println!("no calls traced, {path} is not written");
+        }
+
+        match rec.write() {
+            Ok(count) => println!("trace with {count} events written to {path}"),
+            Err(e) => println!("failed to write trace: {e}"),
+        }
+    }
+
+    /// Evaluate watches matching current location
+    fn eval_watches(&self, dbg: &dbg::SqDebugger) {
+        if self.during_eval {
+            return;
+        }
+
+        let location = self.last_event.read().unwrap().event();
+        let mut capture = None;
+
+        for SqWatch { number, location: watch_loc, expr } in self.watches.iter() {
+            // Matched same way as breakpoint with this location
+            let matched = match (watch_loc, &location) {
+                (None, _) => true,
+                (Some(loc), Some(location)) => SqBreakpoint::from(loc.clone()).match_event(location),
+                (Some(_), None) => false,
+            };
+            if !matched {
+                continue;
+            }
+
+            // Locals are requested only if any watch matched
+            let capture = capture.get_or_insert_with(|| Self::frame_capture(dbg));
+
+            match dbg.execute(Self::wrap_expression(expr), capture.clone(), 1) {
+                Ok(val) => println!("watch {number}: {expr} = {val}"),
+                // Locals of watch may be out of scope at this halt
+                Err(_) => println!("watch {number}: {expr} = <unavailable>"),
+            }
+        }
+    }
+
     /// Execute commands stored to display
     fn do_stored_actions(&mut self, dbg: &mut dbg::SqDebugger) {
         let stored: Vec<_> = self.display_cmds.iter().cloned().collect();
@@ -881,9 +4170,19 @@ impl DebuggerFrontend {
 impl DebuggerFrontend {
     /// Connect new frontend to debugger middleware
     pub fn connect(middleware: dbg::SqDebugger) -> ! {
-        let recv = middleware.event_rx().clone();
+        let event_rx = Arc::new(RwLock::new(middleware.event_rx().clone()));
+        let event_rx_shared = event_rx.clone();
+        let trace_dedup = Arc::new(atomic::AtomicBool::new(false));
+        let trace_dedup_shared = trace_dedup.clone();
+        let quiet_step = Arc::new(atomic::AtomicBool::new(false));
+        let quiet_step_shared = quiet_step.clone();
+        let halt_events = Arc::new(atomic::AtomicUsize::new(0));
+        let halt_events_shared = halt_events.clone();
+        let mut dedup = EventDedup::default();
         let shared_dbg = Arc::new(Mutex::new(middleware));
         let shared_dbg_ctrlc = shared_dbg.clone();
+        let halt_requested = Arc::new(atomic::AtomicBool::new(false));
+        let halt_requested_ctrlc = halt_requested.clone();
 
         // Set ctrl+c handler to stop execution        
         ctrlc::set_handler(move || {
@@ -891,62 +4190,189 @@ impl DebuggerFrontend {
 
             if dbg.exec_state() == dbg::ExecState::Running {            
                 println!("Execution halted");
+                halt_requested_ctrlc.store(true, atomic::Ordering::Relaxed);
                 dbg.halt();
             }
         }).expect("failed to set ctrl+c handler");
 
         let last_event = Arc::new(RwLock::new(BrkSpec::default()));
         let last_event_shared = last_event.clone();
+        let trace_rec = Arc::new(Mutex::new(None::<TraceRecorder>));
+        let trace_rec_shared = trace_rec.clone();
+        let event_log = Arc::new(Mutex::new(EventLog::new(EVENT_LOG_SIZE)));
+        let event_log_shared = event_log.clone();
+        let stop_reason = Arc::new(RwLock::new(StopReason::Step));
+        let stop_reason_shared = stop_reason.clone();
+        let hit_snapshots = Arc::new(Mutex::new(vec![]));
+        let hit_snapshots_shared = hit_snapshots.clone();
         // Debugger frontend thread
         std::thread::spawn(move || {
             let mut front = Self { 
                 last_cmd: None,
                 buffers: ScriptBuffers::new(),
                 during_eval: false,
+                detached: false,
                 srcs: SourceDB::new(),
                 display_cmds: SavedCommands::new(),
-                last_event: last_event_shared
+                last_event: last_event_shared,
+                max_buffers: DEFAULT_MAX_BUFFERS,
+                watches: WatchStore::new(),
+                trace_rec: trace_rec_shared,
+                event_log: event_log_shared,
+                stop_reason: stop_reason_shared,
+                snapshots: IndexMap::new(),
+                vlocals: vec![],
+                aliases: vec![],
+                hit_snapshots: hit_snapshots_shared,
+                event_rx: event_rx_shared,
+                strict_specs: false,
+                cmd_failed: false,
+                stop_on_error: false,
+                running_batches: vec![],
+                trace_dedup: trace_dedup_shared,
+                quiet_step: quiet_step_shared,
+                halt_events: halt_events_shared,
+                armed_depth: None,
+                editor: None,
+                until_bp: None,
+                frame: None,
+                output_format: OutputFormat::Text,
             };
             
             let mut arg_str = String::new();
+            let mut reader = CmdReader::new();
         
             println!("Debugger attached, type `help` to get available commands list");
 
+            front.run_config(&mut shared_dbg.lock().unwrap());
+
             loop {
                 std::thread::sleep(Duration::from_millis(10));
             
                 let mut dbg = shared_dbg.lock().unwrap();
-            
-                if let dbg::ExecState::Halted = dbg.exec_state() {
-                    std::io::stdin().read_line(&mut arg_str).expect("failed to read cmd line");
+                front.attach_bound(&mut dbg);
+
+                let halted = dbg.exec_state() == dbg::ExecState::Halted;
+                if halted {
+                    front.finish_trace(&dbg);
+                    front.trace_dedup.store(false, atomic::Ordering::Relaxed);
+                    front.save_hit_snapshots(&dbg);
+                    front.check_depth_trigger(&dbg);
+                    front.clear_until(&dbg);
+                    front.report_watchpoints(&dbg);
+                    front.report_error_halt(&dbg);
+                    front.reset_frame();
+                }
+
+                // Prompt stays available while detached, e.g. for `reattach`
+                if halted || front.detached {
+                    reader.set_candidates(
+                        dbg.breakpoints().breakpoints().iter().map(|bp| bp.number.to_string()).collect(),
+                        front.buffers.keys(),
+                    );
+                    // Input interrupted by ctrl+c or ctrl+d is dropped, not repeated
+                    match reader.read_line(&mut arg_str) {
+                        Ok(true) => (),
+                        Ok(false) => continue,
+                        Err(e) => {
+                            println!("failed to read command: {e}");
+                            continue;
+                        }
+                    }
 
                     if !arg_str.trim().is_empty() {
-                        match front.parse_args(&arg_str) {
-                            Ok(args) => front.do_actions(&mut dbg, args, true),
-                            Err(e) => println!("{e}"),
-                        };
+                        if let Err(e) = front.run_line(&mut dbg, &arg_str, true) {
+                            println!("{e}");
+                        }
                     } else { 
                         front.repeat_last_cmd(&mut dbg);
                     }
 
-                    // To allow vm to make step, etc
-                    std::thread::sleep(Duration::from_millis(10));
-                    
-                    front.do_stored_actions(&mut dbg);
-                    
-                
-                    arg_str.clear();
+                    // To allow vm to make step, etc
+                    std::thread::sleep(Duration::from_millis(10));
+                    
+                    front.do_stored_actions(&mut dbg);
+                    front.eval_watches(&dbg);
+                    
+                
+                    arg_str.clear();
+                }
+            }
+        });
+
+        // Print received events
+        loop {
+            // Receiver is replaced on reattach, so do not block on old one forever
+            let recv = event_rx.read().unwrap().clone();
+            let received = recv.recv_timeout(Duration::from_millis(100));
+
+            // Print pending run when trace stops or no events for a while
+            if received.is_err() || !trace_dedup.load(atomic::Ordering::Relaxed) {
+                if let Some(line) = dedup.flush() {
+                    println!("{line}");
+                }
+            }
+
+            if let Ok(dbg::DebugEventBundle { snapshot: Some((number, locals)), time, .. }) = &received {
+                hit_snapshots.lock().unwrap().push(HitSnapshot { 
+                    number: *number, 
+                    received: *time, 
+                    locals: locals.clone(),
+                });
+            }
+
+            if let Ok(dbg::DebugEventBundle { cond_error: Some((number, e)), .. }) = &received {
+                println!("condition of breakpoint {number} failed, treated as true: {e}");
+            }
+
+            if let Ok(dbg::DebugEventBundle { log: Some(msg), .. }) = &received {
+                println!("{msg}");
+            }
+
+            // Logpoint hit while running is not an event to report,
+            // but step or halt on its line is handled as usual
+            if let Ok(dbg::DebugEventBundle { halted: false, traced: false, .. }) = &received {
+                continue;
+            }
+
+            if let Ok(dbg::DebugEventBundle { event: e, bp, args, halted, time, .. }) = received { 
+                if let Some(bp) = &bp {
+                    println!("Reached debugger breakpoint {}", bp.number);
+                }
+
+                *stop_reason.write().unwrap() = match &bp {
+                    Some(bp) => StopReason::Breakpoint(bp.number),
+                    None if halt_requested.swap(false, atomic::Ordering::Relaxed) => StopReason::Halt,
+                    None => StopReason::Step,
+                };
+
+                // Intermediate events of `step N` are only logged
+                let quiet = quiet_step.swap(false, atomic::Ordering::Relaxed) && bp.is_none();
+
+                if !quiet {
+                    // Calls with printed arguments are never collapsed
+                    if trace_dedup.load(atomic::Ordering::Relaxed) && bp.is_none() && args.is_none() {
+                        if let Some(line) = dedup.push(&e) {
+                            println!("{line}");
+                        }
+                    } else {
+                        if let Some(line) = dedup.flush() {
+                            println!("{line}");
+                        }
+                        println!("{e}");
+                    }
+                }
+
+                if let (false, Some(args)) = (quiet, args) {
+                    Self::print_call_args(&args);
                 }
-            }
-        });
 
-        // Print received events
-        loop {
-            if let Ok((e, bp)) = recv.recv() { 
-                if let Some(bp) = bp {
-                    println!("Reached debugger breakpoint {}", bp.number);
+                if let Some(rec) = trace_rec.lock().unwrap().as_mut() {
+                    rec.record(&e, time);
                 }
-                println!("{e}");
+
+                event_log.lock().unwrap().push(e.clone());
+
                 // TODO: Optimize lock usage
                 let mut write_lock = last_event.write().unwrap();
                 match e.event {
@@ -961,6 +4387,12 @@ impl DebuggerFrontend {
                         write_lock.func = None;
                     },
                 }
+                drop(write_lock);
+
+                // Event is fully handled, stepping commands may read stop reason
+                if halted {
+                    halt_events.fetch_add(1, atomic::Ordering::Release);
+                }
             }
         }   
     }
@@ -977,14 +4409,17 @@ impl IntoListItems for &dbg::BreakpointStore {
     fn list_items(self) {
         const BP_NUMBER_FIELD: usize = 8;
         const BP_ENABLED_FIELD: usize = 10;
+        const BP_HITS_FIELD: usize = 8;
 
         if self.breakpoints().is_empty() {
             return println!("no breakpoints registered")
         }
 
-        println!("{:<BP_NUMBER_FIELD$}{:<BP_ENABLED_FIELD$}location", "number", "enabled");   
-        for SqBreakpoint { line, fn_name, src_file, enabled, number } in self.breakpoints() {
-            print!("{number:<BP_NUMBER_FIELD$}{enabled:<BP_ENABLED_FIELD$}");
+        println!("{:<BP_NUMBER_FIELD$}{:<BP_ENABLED_FIELD$}{:<BP_HITS_FIELD$}location", "number", "enabled", "matched");   
+        for SqBreakpoint { 
+            line, fn_name, src_file, enabled, number, snapshot, condition, hit_count, ignore_count, temporary, log, .. 
+        } in self.breakpoints() {
+            print!("{number:<BP_NUMBER_FIELD$}{enabled:<BP_ENABLED_FIELD$}{hit_count:<BP_HITS_FIELD$}");
     
             if src_file.is_some() {
                 print!("file:");
@@ -997,6 +4432,26 @@ impl IntoListItems for &dbg::BreakpointStore {
                 print!("{part}");
             }
 
+            if *ignore_count > 0 {
+                print!(" ignore {ignore_count}");
+            }
+
+            if let Some(cond) = condition {
+                print!(" if {cond}");
+            }
+
+            if let Some(log) = log {
+                print!(" log \"{log}\"");
+            }
+
+            if *snapshot {
+                print!(" [snapshot]");
+            }
+
+            if *temporary {
+                print!(" [temporary]");
+            }
+
             println!();
         }
     }
@@ -1012,24 +4467,31 @@ impl IntoListItems for &Vec<SqLocalVarWithLvl> {
     /// ...
     /// ```
     fn list_items(self) {
-        let mut curr_lvl = 0; // Non-existent
-        for SqLocalVarWithLvl { var: SqLocalVar { name, val }, lvl } in self {
-            if *lvl != curr_lvl {
-                println!("Level {lvl} locals:");
-                curr_lvl = *lvl;
-            }
-
-            print!("    {name}: {:?}", val.get_type());
+        print!("{}", format_locals(self));
+    }
+}
 
-            match val {
-                DynSqVar::Integer(i) => println!(" = {i}"),
-                DynSqVar::Float(f) => println!(" = {f}"),
-                DynSqVar::Bool(b) => println!(" = {b}"),
-                DynSqVar::String(s) => println!(" = \"{s}\""),
-                _ => println!(),
-            }
+/// Format locals as listed by [IntoListItems]
+fn format_locals(locals: &[SqLocalVarWithLvl]) -> String {
+    let mut out = String::new();
+    let mut curr_lvl = 0; // Non-existent
+    for SqLocalVarWithLvl { var: SqLocalVar { name, val }, lvl } in locals {
+        if *lvl != curr_lvl {
+            writeln!(out, "Level {lvl} locals:").unwrap();
+            curr_lvl = *lvl;
         }
+
+        write!(out, "    {name}: {:?}", val.get_type()).unwrap();
+
+        match val {
+            DynSqVar::Integer(i) => writeln!(out, " = {i}"),
+            DynSqVar::Float(f) => writeln!(out, " = {f}"),
+            DynSqVar::Bool(b) => writeln!(out, " = {b}"),
+            DynSqVar::String(s) => writeln!(out, " = \"{s}\""),
+            _ => writeln!(out),
+        }.unwrap();
     }
+    out
 }
 
 // TODO: Generalize all auto-increment-number-associative structs 
@@ -1079,17 +4541,436 @@ impl IntoListItems for &SavedCommands {
     }
 }
 
+/// Chrome Tracing duration event
+#[derive(Serialize)]
+struct ChromeTraceEvent {
+    name: String,
+    cat: &'static str,
+    /// Phase: `B` - begin, `E` - end
+    ph: &'static str,
+    /// Timestamp in microseconds
+    ts: u128,
+    pid: u32,
+    tid: u32,
+}
+
+/// Records function calls and returns received during tracing
+struct TraceRecorder {
+    path: String,
+    format: TraceFormat,
+    start: Instant,
+    events: Vec<ChromeTraceEvent>,
+    /// Names of functions called, but not returned yet
+    stack: Vec<String>,
+}
+
+impl TraceRecorder {
+    pub fn new(path: String, format: TraceFormat) -> Self {
+        Self { path, format, start: Instant::now(), events: vec![], stack: vec![] }
+    }
+
+    /// Check if no events recorded
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Record call or return event, that hook received at `time`
+    pub fn record(&mut self, event: &DebugEventWithSrc, time: Instant) {
+        let ts = time.saturating_duration_since(self.start).as_micros();
+        let (name, ph) = match &event.event {
+            DebugEvent::FnCall(name, _) => {
+                self.stack.push(name.clone());
+                (name.clone(), "B")
+            }
+            DebugEvent::FnRet(name, _) => {
+                // Function was called before trace start
+                if self.stack.pop().is_none() {
+                    self.events.insert(0, Self::event(name.clone(), "B", 0));
+                }
+                (name.clone(), "E")
+            }
+            DebugEvent::Line(_) => return,
+        };
+
+        self.events.push(Self::event(name, ph, ts));
+    }
+
+    fn event(name: String, ph: &'static str, ts: u128) -> ChromeTraceEvent {
+        ChromeTraceEvent { name, cat: "squirrel", ph, ts, pid: 1, tid: 1 }
+    }
+
+    /// Close unfinished calls and write trace to file.
+    /// Returns count of written events
+    pub fn write(mut self) -> Result<usize> {
+        // Trace stopped inside of these calls
+        let ts = self.start.elapsed().as_micros();
+        while let Some(name) = self.stack.pop() {
+            self.events.push(Self::event(name, "E", ts));
+        }
+
+        let f = File::create(&self.path)?;
+        match self.format {
+            TraceFormat::Chrome => {
+                #[derive(Serialize)]
+                #[serde(rename_all = "camelCase")]
+                struct ChromeTrace<'a> {
+                    trace_events: &'a [ChromeTraceEvent],
+                }
+                serde_json::to_writer(&f, &ChromeTrace { trace_events: &self.events })?;
+            }
+        }
+        Ok(self.events.len())
+    }
+}
+
+/// Cause of the last execution halt
+#[derive(Clone, Copy, Debug)]
+enum StopReason {
+    Breakpoint(u32),
+    Step,
+    /// Halted by ctrl+c
+    Halt,
+    /// Call stack depth trigger
+    Depth(usize),
+    /// Value of watched variable changed
+    Watchpoint(u32),
+    /// Error was not caught by script
+    Error,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Breakpoint(n) => write!(f, "bp#{n}"),
+            Self::Step => write!(f, "step"),
+            Self::Halt => write!(f, "halt"),
+            Self::Depth(d) => write!(f, "depth {d}"),
+            Self::Watchpoint(n) => write!(f, "wp#{n}"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Bounded log of received debug events with absolute indices
+struct EventLog {
+    events: VecDeque<DebugEventWithSrc>,
+    /// Count of events dropped from the front of the log
+    dropped: usize,
+    capacity: usize,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { events: VecDeque::with_capacity(capacity), dropped: 0, capacity }
+    }
+
+    /// Append event, dropping the oldest one if log is full
+    pub fn push(&mut self, event: DebugEventWithSrc) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    /// Index of the next logged event
+    pub fn end(&self) -> usize {
+        self.dropped + self.events.len()
+    }
+
+    /// Get events in half-open range along with their indices.
+    /// Negative bounds are relative to the end of log
+    pub fn slice(&self, start: isize, end: Option<isize>) -> impl Iterator<Item = (usize, &DebugEventWithSrc)> {
+        let resolve = |idx: isize| if idx < 0 {
+            self.end().saturating_sub(idx.unsigned_abs())
+        } else {
+            idx as usize
+        };
+
+        let start = resolve(start).max(self.dropped);
+        let end = end.map_or(self.end(), resolve).min(self.end());
+
+        self.events.iter()
+            .enumerate()
+            .map(|(i, e)| (i + self.dropped, e))
+            .skip(start - self.dropped)
+            .take(end.saturating_sub(start))
+    }
+}
+
+/// Collapses runs of identical consecutive events into one line with repeat count
+#[derive(Default)]
+struct EventDedup {
+    last: Option<DebugEventWithSrc>,
+    count: usize,
+}
+
+impl EventDedup {
+    /// Add event to current run. If run is ended, returns its line to print
+    pub fn push(&mut self, event: &DebugEventWithSrc) -> Option<String> {
+        if self.last.as_ref() == Some(event) {
+            self.count += 1;
+            return None;
+        }
+
+        let out = self.flush();
+        self.last = Some(event.clone());
+        self.count = 1;
+        out
+    }
+
+    /// End current run, returns its line to print
+    pub fn flush(&mut self) -> Option<String> {
+        let last = self.last.take()?;
+        Some(match self.count {
+            1 => last.to_string(),
+            n => format!("{last} (x{n})"),
+        })
+    }
+}
+
+/// Expression evaluated on halt
+struct SqWatch {
+    number: u32,
+    /// If specified, evaluate only when halted at matching location
+    location: Option<BrkSpec>,
+    expr: String,
+}
+
+/// Struct allows to manage watch expressions
+struct WatchStore {
+    store: Vec<SqWatch>,
+    counter: u32,
+}
+
+impl WatchStore {
+    /// Create new WatchStore
+    pub fn new() -> Self {
+        Self { store: vec![], counter: 1 }
+    }
+
+    /// Add watch expression. Returns added watch number
+    pub fn add(&mut self, location: Option<BrkSpec>, expr: String) -> u32 {
+        self.store.push(SqWatch { number: self.counter, location, expr });
+        self.counter += 1;
+        self.counter - 1
+    }
+
+    /// Delete watch by number. If number not specified, delete all
+    pub fn delete(&mut self, number: Option<u32>) {
+        self.store.retain(|w| matches!(number, Some(num) if w.number != num))
+    }
+
+    /// Get iterator over watches
+    pub fn iter(&self) -> impl Iterator<Item = &SqWatch> {
+        self.store.iter()
+    }
+}
+
+impl IntoListItems for &WatchStore {
+    fn list_items(self) {
+        const NUM_FIELD: usize = 8;
+
+        if self.store.is_empty() {
+            return println!("no watches registered");
+        }
+
+        println!("{:<NUM_FIELD$}expression", "number");
+        for SqWatch { number, location, expr } in &self.store {
+            print!("{number:<NUM_FIELD$}{expr}");
+            if let Some(loc) = location {
+                print!(" (at {loc})");
+            }
+            println!();
+        }
+    }
+}
+
+impl IntoListItems for &dbg::WatchpointStore {
+    fn list_items(self) {
+        const NUM_FIELD: usize = 8;
+
+        if self.watchpoints().is_empty() {
+            return println!("no watchpoints registered");
+        }
+
+        println!("{:<NUM_FIELD$}target", "number");
+        for SqWatchpoint { number, path, func, value, .. } in self.watchpoints() {
+            println!("{number:<NUM_FIELD$}{} in {} = {value}", format_path(path), func.as_deref().unwrap_or("??"));
+        }
+    }
+}
+
+/// Commands that take breakpoint number as first argument
+const BP_NUMBER_COMMANDS: &[&str] = &[
+    "breakpoint-enable", "breakpoint-disable", "breakpoint-ignore", "breakpoint-clear",
+];
+
+/// Completes command names and aliases, breakpoint numbers and buffers
+struct CmdCompleter {
+    cli: Command,
+    breakpoints: Vec<String>,
+    buffers: Vec<String>,
+}
+
+impl CmdCompleter {
+    /// Names and visible aliases of subcommands
+    fn names(cmd: &Command) -> Vec<String> {
+        cmd.get_subcommands()
+            .flat_map(|c| std::iter::once(c.get_name()).chain(c.get_visible_aliases()))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl rustyline::completion::Completer for CmdCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &rustyline::Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let prev: Vec<_> = line[..start].split_whitespace().collect();
+
+        let candidates = match &prev[..] {
+            [] => Self::names(&self.cli),
+            [cmd, rest @ ..] => match self.cli.find_subcommand(cmd) {
+                Some(sub) if sub.has_subcommands() && rest.is_empty() => Self::names(sub),
+                Some(sub) if BP_NUMBER_COMMANDS.contains(&sub.get_name()) && rest.is_empty()
+                    => self.breakpoints.clone(),
+                // Buffer subcommands take buffer as first argument
+                Some(sub) if sub.get_name() == "buffer" => match rest {
+                    [bufcmd] if sub.find_subcommand(bufcmd)
+                        .is_some_and(|c| c.get_arguments().any(|a| a.get_id() == "buf"))
+                        => self.buffers.clone(),
+                    _ => vec![],
+                }
+                _ => vec![],
+            }
+        };
+
+        Ok((start, candidates.into_iter().filter(|c| c.starts_with(word)).collect()))
+    }
+}
+
+impl rustyline::hint::Hinter for CmdCompleter {
+    type Hint = String;
+}
+
+impl rustyline::highlight::Highlighter for CmdCompleter {}
+
+impl rustyline::validate::Validator for CmdCompleter {}
+
+impl rustyline::Helper for CmdCompleter {}
+
+/// Reader of command lines with history, persisted between sessions.
+/// Falls back to plain stdin if line editor is unavailable
+struct CmdReader {
+    editor: Option<rustyline::Editor<CmdCompleter, rustyline::history::DefaultHistory>>,
+    history: Option<std::path::PathBuf>,
+}
+
+impl CmdReader {
+    fn new() -> Self {
+        let history = std::env::var_os("USERPROFILE").or_else(|| std::env::var_os("HOME"))
+            .map(|h| Path::new(&h).join(HISTORY_FILENAME));
+
+        let editor = match rustyline::Editor::new() {
+            Ok(mut editor) => {
+                editor.set_helper(Some(CmdCompleter {
+                    cli: DebuggerFrontend::cli(),
+                    breakpoints: vec![],
+                    buffers: vec![],
+                }));
+                if let Some(path) = &history {
+                    // History file does not exist before first save
+                    let _ = editor.load_history(path);
+                }
+                Some(editor)
+            }
+            Err(e) => {
+                println!("failed to init line editor, history is disabled: {e}");
+                None
+            }
+        };
+
+        Self { editor, history }
+    }
+
+    /// Set completed breakpoint numbers and buffers
+    fn set_candidates(&mut self, breakpoints: Vec<String>, buffers: Vec<String>) {
+        if let Some(helper) = self.editor.as_mut().and_then(|e| e.helper_mut()) {
+            helper.breakpoints = breakpoints;
+            helper.buffers = buffers;
+        }
+    }
+
+    /// Append line to `buf` with trailing line break, same as [std::io::Stdin::read_line].
+    /// Returns `false` if input was interrupted or closed by ctrl+d
+    fn read_line(&mut self, buf: &mut String) -> Result<bool> {
+        let Some(editor) = &mut self.editor else {
+            std::io::stdin().read_line(buf)?;
+            return Ok(true);
+        };
+
+        match editor.readline("") {
+            Ok(line) => {
+                // Empty line repeats last command, so it is not worth recalling
+                if !line.trim().is_empty() {
+                    if let Err(e) = editor.add_history_entry(line.as_str()) {
+                        println!("failed to add history entry: {e}");
+                    }
+                    if let Some(path) = &self.history {
+                        if let Err(e) = editor.save_history(path) {
+                            println!("failed to save history: {e}");
+                        }
+                    }
+                }
+                buf.push_str(&line);
+                buf.push('\n');
+                Ok(true)
+            }
+            Err(rustyline::error::ReadlineError::Interrupted | rustyline::error::ReadlineError::Eof) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
 /// Struct that holds multiple string with script
 #[derive(Clone, Serialize, Deserialize)]
 struct ScriptBuffers {
     store: Vec<(u32, String)>,
     counter: u32,
+    /// Numbers of buffers that were edited after creation
+    #[serde(default)]
+    modified: Vec<u32>,
+    /// Optional unique names of buffers
+    #[serde(default)]
+    names: Vec<(u32, String)>,
 }
 
 impl ScriptBuffers {
     /// Create new ScriptBuffers
     pub fn new() -> Self {
-        Self { store: vec![], counter: 1 }
+        Self { store: vec![], counter: 1, modified: vec![], names: vec![] }
+    }
+
+    /// Make room for new buffer if buffers count reached `limit`.
+    /// Only buffers that were never edited can be evicted, oldest goes first.
+    ///
+    /// Returns number of evicted buffer
+    pub fn evict(&mut self, limit: usize) -> Result<Option<u32>> {
+        if self.store.len() < limit {
+            return Ok(None);
+        }
+
+        let Some(pos) = self.store.iter().position(|(n, _)| !self.modified.contains(n)) else {
+            bail!("limit of {limit} buffers reached and all buffers are edited, delete some manually");
+        };
+
+        let (num, _) = self.store.remove(pos);
+        self.names.retain(|(n, _)| *n != num);
+        Ok(Some(num))
     }
 
     /// Add buffer. Returns added buffer number
@@ -1108,12 +4989,68 @@ impl ScriptBuffers {
     pub fn replace(&mut self, number: u32, buf: String) {
         if let Some(b) = self.store.iter_mut().find(|(n, _)| *n == number) {
             *b = (number, buf);
+            if !self.modified.contains(&number) {
+                self.modified.push(number);
+            }
         }
     }
 
     /// Delete buffer by number
     pub fn delete(&mut self, number: u32) {
-        self.store.retain(|(n, _)| *n != number)
+        self.store.retain(|(n, _)| *n != number);
+        self.modified.retain(|n| *n != number);
+        self.names.retain(|(n, _)| *n != number);
+    }
+
+    /// Find existing buffer number by number or name
+    pub fn resolve(&self, buf: &str) -> Option<u32> {
+        let number = buf.parse().ok()
+            .or_else(|| self.names.iter().find(|(_, name)| name == buf).map(|(n, _)| *n))?;
+
+        self.store.iter().any(|(n, _)| *n == number).then_some(number)
+    }
+
+    /// Numbers and names of buffers
+    pub fn keys(&self) -> Vec<String> {
+        self.store.iter()
+            .map(|(n, _)| n.to_string())
+            .chain(self.names.iter().map(|(_, name)| name.clone()))
+            .collect()
+    }
+
+    /// Get buffer name
+    pub fn name(&self, number: u32) -> Option<&str> {
+        self.names.iter().find(|(n, _)| *n == number).map(|(_, name)| name.as_str())
+    }
+
+    /// Set unique name of buffer
+    pub fn set_name(&mut self, number: u32, name: String) -> Result<()> {
+        if name.parse::<u32>().is_ok() {
+            bail!("name can't be a number");
+        }
+        if self.names.iter().any(|(n, other)| *other == name && *n != number) {
+            bail!("name {name} is already used");
+        }
+
+        self.names.retain(|(n, _)| *n != number);
+        self.names.push((number, name));
+        Ok(())
+    }
+
+    /// Change buffer number, swapping with buffer that has `new` number
+    pub fn renumber(&mut self, old: u32, new: u32) -> Result<()> {
+        let Some(next) = new.checked_add(1) else {
+            bail!("number {new} is too big");
+        };
+        let swap = |n: &mut u32| if *n == old { *n = new } else if *n == new { *n = old };
+
+        self.store.iter_mut().for_each(|(n, _)| swap(n));
+        self.modified.iter_mut().for_each(swap);
+        self.names.iter_mut().for_each(|(n, _)| swap(n));
+
+        self.store.sort_by_key(|(n, _)| *n);
+        self.counter = self.counter.max(next);
+        Ok(())
     }
 }
 
@@ -1126,10 +5063,13 @@ impl IntoListItems for &ScriptBuffers {
             return;
         }
 
-        println!("{:<NUM_FIELD$}content", "number");
+        const NAME_FIELD: usize = 16;
+
+        println!("{:<NUM_FIELD$}{:<NAME_FIELD$}content", "number", "name");
         for (n, buf) in &self.store {
             let line = buf.lines().next();
-            println!("{n:<NUM_FIELD$}{}", if let Some(l) = line{ l } else { "<empty>" });
+            let name = self.name(*n).unwrap_or("");
+            println!("{n:<NUM_FIELD$}{name:<NAME_FIELD$}{}", if let Some(l) = line{ l } else { "<empty>" });
         }
     }
 }
@@ -1139,6 +5079,14 @@ impl IntoListItems for &ScriptBuffers {
 enum SqPathToken<'lex> {   
     #[regex("[0-9]+", |lex| lex.slice().parse())]
     Number(usize),
+
+    /// Array index from the end, e.g. `-1` for last element
+    #[regex("-[0-9]+", |lex| lex.slice().parse())]
+    NegIndex(isize),
+
+    /// Array slice `start:end`, bounds may be negative or omitted
+    #[regex("-?[0-9]*:-?[0-9]*", |lex| lex.slice())]
+    Slice(&'lex str),
     
     #[regex(r"\.")]
     Dot,
@@ -1146,13 +5094,75 @@ enum SqPathToken<'lex> {
     #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice())]
     Seg(&'lex str),
     
-    #[regex(r#""([^"]*)""#, |lex| lex.slice())]
+    /// Key with any characters: `"my key"`, `["my.key"]` or `['my key']`.
+    /// Quotes inside are escaped with `\`
+    #[regex(r#""([^"\\]|\\.)*""#, |lex| lex.slice())]
+    #[regex(r#"\[ *"([^"\\]|\\.)*" *\]"#, |lex| lex.slice())]
+    #[regex(r#"\[ *'([^'\\]|\\.)*' *\]"#, |lex| lex.slice())]
     QuotedSeg(&'lex str),
     
     #[error]
     Error,
 }
 
+impl SqPathToken<'_> {
+    /// Slot key of path segment. Quoted segments are always string keys
+    fn key(&self) -> Option<SqPathKey> {
+        match self {
+            Self::Number(idx) => isize::try_from(*idx).ok().map(SqPathKey::Index),
+            Self::NegIndex(idx) => Some(SqPathKey::Index(*idx)),
+            Self::Slice(slice) => Some(SqPathKey::Slice(slice.to_string())),
+            Self::Seg(_) | Self::QuotedSeg(_) => self.text().map(SqPathKey::Name),
+            _ => None,
+        }
+    }
+
+    /// Text of path segment. Quoted segments are unquoted and unescaped
+    fn text(&self) -> Option<String> {
+        match self {
+            Self::Number(idx) => Some(idx.to_string()),
+            Self::NegIndex(idx) => Some(idx.to_string()),
+            Self::Slice(slice) => Some(slice.to_string()),
+            Self::Seg(seg) => Some(seg.to_string()),
+            Self::QuotedSeg(seg) => {
+                let quoted = seg.trim_start_matches('[').trim_end_matches(']').trim();
+                let mut chars = quoted[1..quoted.len() - 1].chars();
+                let mut key = String::new();
+                while let Some(c) = chars.next() {
+                    key.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+                }
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Join path keys back into path, e.g. for printing watchpoints
+fn format_path(path: &[SqPathKey]) -> String {
+    let mut text = String::new();
+    for key in path {
+        let key = key.to_string();
+        if !text.is_empty() && !key.starts_with('[') {
+            text.push('.');
+        }
+        text.push_str(&key);
+    }
+    text
+}
+
+/// Path to child of container at `path` by `key`, in form accepted by path parser.
+/// Keys, that are not identifiers, are quoted in brackets: `path["my key"]`
+fn child_path(path: &str, key: &DynSqVar) -> String {
+    match key {
+        DynSqVar::String(s) if DynSqVar::is_identifier(s) && path.is_empty() => s.clone(),
+        DynSqVar::String(s) if DynSqVar::is_identifier(s) => format!("{path}.{s}"),
+        DynSqVar::String(s) => format!("{path}[\"{}\"]", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        other if path.is_empty() => other.to_string(),
+        other => format!("{path}.{other}"),
+    }
+}
+
 /// Tokens for specification of breakpoint
 #[derive(Debug, Logos)]
 enum SqBrkSpecToken<'lex> {
@@ -1175,7 +5185,7 @@ enum SqBrkSpecToken<'lex> {
     Error,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct BrkSpec {
     file: Option<String>,
     func: Option<String>,
@@ -1251,6 +5261,18 @@ impl std::fmt::Display for BrkSpec {
 }
 
 impl BrkSpec {
+    /// Restore event of location, that was converted to spec.
+    /// Returns `None` if neither function nor line is known
+    pub fn event(&self) -> Option<DebugEventWithSrc> {
+        let line = self.line.map(|l| l as isize);
+        let event = match (&self.func, line) {
+            (Some(func), line) => DebugEvent::FnCall(func.clone(), line),
+            (None, Some(line)) => DebugEvent::Line(line),
+            (None, None) => return None,
+        };
+        Some(DebugEventWithSrc { event, src: self.file.clone() })
+    }
+
     pub fn parse(input: &str) -> Result<Self, ()> {
         use SqBrkSpecToken::*;
         let parts: Result<Vec<_>, ()> = SqBrkSpecToken::lexer(input)
@@ -1550,6 +5572,48 @@ impl SourceDB {
         )
     }
 
+    /// Check if specification can match any of source files.
+    /// Returns reason if it can't
+    pub fn verify(&self, spec: &BrkSpec) -> Result<(), &'static str> {
+        let mut files: Vec<_> = self.iter_files()
+            .filter(|file| match &spec.file {
+                Some(path) => path.chars().eq(file.iter_name()),
+                None => true,
+            })
+            .collect();
+
+        if files.is_empty() {
+            return Err("unknown source file");
+        }
+
+        if let Some(line) = spec.line {
+            files.retain(|file| file.1.text.lines().count() >= line);
+            if files.is_empty() {
+                return Err("line out of range");
+            }
+        }
+
+        if let Some(func) = &spec.func {
+            let defined = files.iter().any(|file| file.1.items.iter()
+                .any(|it| matches!(&it.item, SqItem::Func(f) if &f.name == func))
+            );
+            if !defined {
+                return Err("unknown function");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get text of lines `first..=last` of file, lines are counted from 1
+    pub fn lines(&self, path: &str, first: usize, last: usize) -> Option<&str> {
+        let file = self.iter_files().find(|file| path.chars().eq(file.iter_name()))?;
+        let text = &file.1.text;
+        let start = text.split_inclusive('\n').take(first.saturating_sub(1)).map(str::len).sum();
+        let len = text[start..].split_inclusive('\n').take((last + 1).saturating_sub(first)).map(str::len).sum::<usize>();
+        (len > 0).then(|| &text[start..start + len])
+    }
+
     // TODO: Add special cases like class constructor
     /// Return iterator over matched functions: `(path, slice)`
     pub fn find<'spec>(
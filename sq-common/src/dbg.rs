@@ -1,19 +1,50 @@
-use std::{time::Duration, sync::{Arc, Mutex, MutexGuard}};
+use std::{
+    time::{Duration, Instant},
+    sync::{Arc, Mutex, MutexGuard, Condvar, atomic::{AtomicU64, AtomicBool, AtomicUsize}},
+    collections::{VecDeque, HashMap, HashSet, BTreeMap},
+};
 use atomic::{Atomic, Ordering};
-use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
+use crossbeam::channel::{bounded, unbounded, Receiver, Sender, RecvTimeoutError};
 use indexmap::IndexMap;
+use log::warn;
 use serde::{Serialize, Deserialize};
 
 use crate::error::{SqDebugResult, SqDebugError};
 use crate::rust_wrap::{
-    vm::{Vm, SqLocalVar, DebugEventWithSrc, DebugEvent, SqStackInfo, SqLocalVarHandle, safety},
+    vm::{Vm, SqLocalVar, DebugEventWithSrc, DebugEvent, SqStackInfo, SqLocalVarHandle, SqRawObject, SqPathKey, SqForeignHook, safety},
     types::*,
     api::VmRawApi,
     push::SqPush,
+    get::SqGet,
 };
 
+/// Default timeout of waiting for hook response
 const RECV_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Count of last thrown errors kept by debugger
+const ERROR_LOG_SIZE: usize = 32;
+
+/// Expansion depth of values compared by watchpoints
+const WATCHPOINT_DEPTH: usize = 1;
+
+/// VMs with attached debugger.
+///
+/// Hook of this crate can't be told apart from foreign one,
+/// so debuggers attached from this crate are tracked
+static ATTACHED_VMS: Mutex<Vec<AttachedVm>> = Mutex::new(vec![]);
+
+/// Number of next attached debugger
+static NEXT_DEBUGGER_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Entry of [ATTACHED_VMS]
+struct AttachedVm {
+    handle: usize,
+    /// Number of debugger, whose hook is set
+    debugger: usize,
+    /// Hook set before first debugger was attached, chained by every debugger
+    foreign_hook: Option<Arc<SqForeignHook>>,
+}
+
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum ExecState {
@@ -21,6 +52,43 @@ pub enum ExecState {
     Halted
 }
 
+/// Execution state, that wakes up halted hook on change
+struct ExecStateCell {
+    state: Mutex<ExecState>,
+    changed: Condvar,
+}
+
+impl ExecStateCell {
+    fn new(state: ExecState) -> Self {
+        Self { state: Mutex::new(state), changed: Condvar::new() }
+    }
+
+    fn load(&self) -> ExecState {
+        *self.state.lock().unwrap()
+    }
+
+    fn store(&self, state: ExecState) {
+        *self.state.lock().unwrap() = state;
+        self.changed.notify_all();
+    }
+
+    /// Wake up halted hook without changing state, e.g. when message is sent
+    fn notify(&self) {
+        let _state = self.state.lock().unwrap();
+        self.changed.notify_all();
+    }
+
+    /// Block while vm is halted and `idle` returns true.
+    ///
+    /// `idle` is checked under the lock, so wakeup from [ExecStateCell::notify] can`t be missed
+    fn wait_halted(&self, idle: impl Fn() -> bool) {
+        let mut state = self.state.lock().unwrap();
+        while *state == ExecState::Halted && idle() {
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
 /// Specification of local to be captured (name, level)
 pub type SqCaptureLocal = (String, usize);
 
@@ -28,24 +96,65 @@ pub type SqCaptureLocal = (String, usize);
 pub struct SqScriptDesc {
     /// Local variables to be captured
     capture: Vec<SqCaptureLocal>,
+    /// Values to be passed to compiled closure as copies, override captured locals
+    values: IndexMap<String, DynSqVar>,
     script: String,
     /// Return value expansion depth
     depth: usize,
     debug: bool,
 }
 
+/// Predicate of events sent during tracing
+pub type TraceFilter = Box<dyn Fn(&DebugEventWithSrc) -> bool + Send>;
+
 pub enum DebugMsg {
     Step,
+    /// Step without descending into called functions
+    StepOver,
+    /// Run until current function returns
+    StepOut,
     Backtrace,
-    Trace,
+    /// Fetch call arguments, send only events matching filter
+    Trace(bool, Option<TraceFilter>),
     /// Level, Depth
     Locals(Option<usize>, usize),
     Eval(SqScriptDesc),
+    /// Level
+    FrameClosure(usize),
+    /// Depth
+    RootTable(usize),
+    /// Compiled functions of closures reachable from root table
+    FunctionProtos,
+    /// Child of root table, fetched without expanding siblings
+    GlobalChild {
+        path: Vec<SqPathKey>,
+        depth: usize,
+    },
+    /// Generate debug info for compiled scripts
+    DebugInfo(bool),
+    /// Remove debug hook and error handler
+    Detach,
+    /// Level, path to local
+    RawLocal(usize, Vec<SqPathKey>),
+    /// Level, path to member of local container, new value
+    SetLocal(usize, Vec<SqPathKey>, DynSqVar),
+    /// Level, path to local or its child
+    AddWatchpoint(usize, Vec<SqPathKey>),
+    /// Local or its child, fetched without expanding siblings
+    LocalChild {
+        /// If not specified, first level with matching path is used
+        lvl: Option<usize>,
+        path: Vec<SqPathKey>,
+        depth: usize,
+        /// Fetch variables captured by closures
+        upvalues: bool,
+    },
 }
 
 /// SqLocalVar annotated with level
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SqLocalVarWithLvl {
+    #[serde(flatten)]
     pub var: SqLocalVar,
     pub lvl: usize,
 }
@@ -71,11 +180,99 @@ impl std::fmt::Display for DebugEventWithSrc {
 
 pub type SqBacktrace = Vec<SqStackInfo>;
 
+/// Error raised by VM, recorded by error handler
+#[derive(Clone, Debug)]
+pub struct SqThrownError {
+    pub error: DynSqVar,
+    /// Call stack at throw time
+    pub backtrace: SqBacktrace,
+}
+
+/// Ring buffer of last thrown errors, oldest first
+pub type SqErrorLog = VecDeque<SqThrownError>;
+
+/// Evaluate condition with locals of function that called the hook
+fn eval_condition(vm: &Vm<safety::Friend>, cond: &str) -> SqDebugResult<bool> {
+    let res = eval_in_frame(vm, format!("return !!({cond});"), 0)?;
+    Ok(matches!(res, DynSqVar::Bool(true)))
+}
+
+/// Format logpoint message: replace `{expr}` with value of expression,
+/// evaluated with locals of function that called the hook
+fn format_log(vm: &Vm<safety::Friend>, template: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|c| open + c) else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+
+        let expr = &rest[open + 1..close];
+        match eval_in_frame(vm, format!("return ({expr});"), 1) {
+            Ok(val) => out.push_str(&val.to_string()),
+            Err(e) => out.push_str(&format!("<{e}>")),
+        }
+        rest = &rest[close + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Compile and call script with locals of function that called the hook
+fn eval_in_frame(vm: &Vm<safety::Friend>, script: String, depth: usize) -> SqDebugResult<DynSqVar> {
+    let top = vm.api().stack_top();
+
+    let res: SqDebugResult<DynSqVar> = try {
+        // Same names as in eval captures
+        let mut env = IndexMap::new();
+        let mut idx = 0;
+        while let Some(SqLocalVarHandle { name, handle }) = vm.get_local_handle(1, idx)? {
+            let name = if name == "this" { "this_1".to_string() } else { name };
+            env.insert(name, handle);
+            idx += 1;
+        }
+
+        vm.compile_closure(script, "condition.nut".into())?;
+        vm.push(env)?;
+        vm.closure_call(1, Some(depth))?
+    };
+
+    // Pop closure and environment, even if call failed
+    vm.api().set_stack_top(top);
+    res
+}
+
+/// Collect call stack infos, starting from function that called the hook
+fn collect_backtrace(vm: &Vm<safety::Friend>) -> SqBacktrace {
+    let mut bt = vec![];
+
+    let stack_size = vm.api().call_stack_len() as usize;
+
+    for lvl in 1..stack_size {
+        if let Ok(info) = vm.get_stack_info(lvl) {
+            bt.push(info);
+        }
+    }
+
+    bt
+}
+
 #[derive(Debug)]
 pub enum DebugResp {
     Backtrace(SqBacktrace),
     Locals(Option<Vec<SqLocalVarWithLvl>>),
     EvalResult(SqDebugResult<DynSqVar>),
+    FrameClosure(SqDebugResult<DynSqVar>),
+    RootTable(SqDebugResult<DynSqVar>),
+    FunctionProtos(SqDebugResult<Vec<SqFunctionProto>>),
+    RawLocal(SqDebugResult<SqRawObject>),
+    AddWatchpoint(SqDebugResult<u32>),
+    LocalChild(SqDebugResult<DynSqVar>),
+    GlobalChild(SqDebugResult<DynSqVar>),
+    SetLocal(SqDebugResult<()>),
 }
 
 impl DebugResp {
@@ -85,6 +282,14 @@ impl DebugResp {
             DebugResp::Backtrace(_) => "Backtrace",
             DebugResp::Locals(_) => "Locals",
             DebugResp::EvalResult(_) => "EvalResult",
+            DebugResp::FrameClosure(_) => "FrameClosure",
+            DebugResp::RootTable(_) => "RootTable",
+            DebugResp::FunctionProtos(_) => "FunctionProtos",
+            DebugResp::RawLocal(_) => "RawLocal",
+            DebugResp::AddWatchpoint(_) => "AddWatchpoint",
+            DebugResp::LocalChild(_) => "LocalChild",
+            DebugResp::GlobalChild(_) => "GlobalChild",
+            DebugResp::SetLocal(_) => "SetLocal",
         }
     }
 }
@@ -97,6 +302,31 @@ pub struct SqBreakpoint {
     pub src_file: Option<String>,
     pub enabled: bool,
     pub number: u32,
+    /// Times execution was halted on this breakpoint, ranked by frontend.
+    /// Reset by frontend on resume, unlike [SqBreakpoint::hit_count] ignored hits are not counted
+    #[serde(default)]
+    pub hits: u32,
+    /// Locals of hit function are captured on each hit and sent with event, see [DebugEventBundle]
+    #[serde(default)]
+    pub snapshot: bool,
+    /// Squirrel expression evaluated in halted function, breakpoint fires only if it is true.
+    /// Failed condition is reported and treated as true
+    #[serde(default)]
+    pub condition: Option<String>,
+    /// Times location matched and condition held, including ignored hits.
+    /// Compared with [SqBreakpoint::ignore_count], so it is not reset with [SqBreakpoint::hits]
+    #[serde(default)]
+    pub hit_count: u32,
+    /// Count of first hits that do not halt execution
+    #[serde(default)]
+    pub ignore_count: u32,
+    /// Remove breakpoint after first halt
+    #[serde(default)]
+    pub temporary: bool,
+    /// Logpoint message template: on hit `{expr}` placeholders are evaluated in
+    /// hit function and message is sent with event, execution is not halted
+    #[serde(default)]
+    pub log: Option<String>,
 }
 
 impl SqBreakpoint {
@@ -108,6 +338,13 @@ impl SqBreakpoint {
             src_file: None,
             enabled: true,
             number: 0,
+            hits: 0,
+            snapshot: false,
+            condition: None,
+            hit_count: 0,
+            ignore_count: 0,
+            temporary: false,
+            log: None,
         }
     }
 
@@ -165,11 +402,13 @@ impl BreakpointStore {
         Self { store: vec![], counter: 1 }
     }
 
-    /// Add new breakpoint
-    pub fn add(&mut self, mut bp: SqBreakpoint) {
-        bp.number = self.counter;
+    /// Add new breakpoint. Returns number assigned to it
+    pub fn add(&mut self, mut bp: SqBreakpoint) -> u32 {
+        let number = self.counter;
+        bp.number = number;
         self.counter += 1;
         self.store.push(bp);
+        number
     }
 
     /// Remove breakpoint by number.
@@ -183,6 +422,53 @@ impl BreakpointStore {
         self.store.iter().find(|bp| bp.enabled && bp.match_event(event))
     }
 
+    /// Get numbers and conditions of enabled breakpoints matching event, in store order.
+    ///
+    /// Conditions are evaluated by caller, then held ones are passed to [BreakpointStore::hit]
+    pub fn match_conditions(&self, event: &DebugEventWithSrc) -> Vec<(u32, Option<String>)> {
+        self.store.iter()
+            .filter(|bp| bp.enabled && bp.match_event(event))
+            .map(|bp| (bp.number, bp.condition.clone()))
+            .collect()
+    }
+
+    /// Count hit of breakpoint, which location matched and condition held.
+    /// Returns breakpoint, if hit is not ignored.
+    ///
+    /// Hit temporary breakpoint is removed from store
+    pub fn hit(&mut self, num: u32) -> Option<SqBreakpoint> {
+        let idx = self.store.iter().position(|bp| bp.number == num && bp.enabled)?;
+        let bp = &mut self.store[idx];
+
+        bp.hit_count += 1;
+        if bp.hit_count <= bp.ignore_count {
+            return None;
+        }
+
+        bp.hits += 1;
+        Some(if bp.temporary {
+            self.store.remove(idx)
+        } else {
+            bp.clone()
+        })
+    }
+
+    /// Reset halt counts of all breakpoints. Counts compared with ignore counts are kept
+    pub fn reset_hits(&mut self) {
+        self.store.iter_mut().for_each(|bp| bp.hits = 0);
+    }
+
+    /// Set count of ignored hits of breakpoint. Returns false if there is no such breakpoint
+    pub fn set_ignore(&mut self, num: u32, count: u32) -> bool {
+        match self.store.iter_mut().find(|bp| bp.number == num) {
+            Some(bp) => {
+                bp.ignore_count = count;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Enable or disable breakpoint by number.
     /// If number not specified, enable/disable all
     pub fn enable(&mut self, num: Option<u32>, en: bool) {
@@ -197,13 +483,324 @@ impl BreakpointStore {
     }
 }
 
-type DebugEventBundle = (DebugEventWithSrc, Option<SqBreakpoint>);
+/// Local variable or its child, halting vm when its value changes
+#[derive(Clone, Debug)]
+pub struct SqWatchpoint {
+    pub number: u32,
+    /// Local name followed by keys in containers
+    pub path: Vec<SqPathKey>,
+    /// Function, which local is watched
+    pub func: Option<String>,
+    /// Last seen value, compared on every event
+    pub value: DynSqVar,
+    /// Call stack length, at which function of local is at level 1
+    frame: usize,
+}
+
+/// Reason of watchpoint report to frontend
+#[derive(Clone, Debug)]
+pub enum WatchpointEvent {
+    Changed {
+        number: u32,
+        path: Vec<SqPathKey>,
+        old: Box<DynSqVar>,
+        new: Box<DynSqVar>,
+    },
+    /// Function of local returned, so watchpoint was removed
+    OutOfScope {
+        number: u32,
+        path: Vec<SqPathKey>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct WatchpointStore {
+    store: Vec<SqWatchpoint>,
+    counter: u32,
+    /// Events not yet taken by frontend
+    events: Vec<WatchpointEvent>,
+}
+
+impl WatchpointStore {
+    /// Create new empty store
+    pub fn new() -> Self {
+        Self { store: vec![], counter: 1, events: vec![] }
+    }
+
+    /// Remove watchpoint by number.
+    /// If number not specified, remove all
+    pub fn remove(&mut self, num: Option<u32>) {
+        self.store.retain(|wp| matches!(num, Some(num) if wp.number != num));
+    }
+
+    /// Get watchpoints store
+    pub fn watchpoints(&self) -> &Vec<SqWatchpoint> {
+        &self.store
+    }
+
+    /// Take changes and removals since last call
+    pub fn take_events(&mut self) -> Vec<WatchpointEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Start watching local at level of hook call. Returns watchpoint number
+    fn add(&mut self, vm: &Vm<safety::Friend>, lvl: usize, path: Vec<SqPathKey>) -> SqDebugResult<u32> {
+        let value = vm.get_local_path(lvl, &path, Some(WATCHPOINT_DEPTH))?;
+        let func = vm.get_stack_info(lvl).ok().and_then(|info| info.funcname);
+        let number = self.counter;
+        self.counter += 1;
+
+        self.store.push(SqWatchpoint {
+            number,
+            path,
+            func,
+            value,
+            frame: vm.api().call_stack_len() as usize - lvl,
+        });
+        Ok(number)
+    }
+
+    /// Compare watched values with current ones at hook call. 
+    /// Returns true if any of them changed
+    fn check(&mut self, vm: &Vm<safety::Friend>) -> bool {
+        if self.store.is_empty() {
+            return false;
+        }
+
+        let stack_len = vm.api().call_stack_len() as usize;
+        let mut changed = false;
+        let events = &mut self.events;
+
+        self.store.retain_mut(|wp| {
+            // Level 0 is hook itself, so function of local has returned
+            let in_scope = stack_len > wp.frame && matches!(
+                vm.get_stack_info(stack_len - wp.frame), Ok(info) if info.funcname == wp.func
+            );
+            let value = match in_scope {
+                true => vm.get_local_path(stack_len - wp.frame, &wp.path, Some(WATCHPOINT_DEPTH)).ok(),
+                false => None,
+            };
+
+            let Some(value) = value else {
+                events.push(WatchpointEvent::OutOfScope { number: wp.number, path: wp.path.clone() });
+                return false;
+            };
+
+            if !value.deep_eq(&wp.value) {
+                let old = std::mem::replace(&mut wp.value, value.clone());
+                events.push(WatchpointEvent::Changed { 
+                    number: wp.number,
+                    path: wp.path.clone(),
+                    old: Box::new(old),
+                    new: Box::new(value),
+                });
+                changed = true;
+            }
+            true
+        });
+
+        changed
+    }
+}
+
+impl Default for WatchpointStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Function entered while timing
+struct ProfiledCall {
+    name: String,
+    /// Time of call event
+    started: Instant,
+    /// Value of [Profiler::paused] at call
+    paused: Duration,
+    /// Inclusive time of returned callees
+    callees: Duration,
+}
+
+/// Function statistics, collected from debug events while enabled
+#[derive(Default)]
+pub struct Profiler {
+    /// Count function calls
+    pub counting: bool,
+    /// Function name -> count of calls
+    calls: HashMap<String, u64>,
+    /// Measure time spent in functions
+    timing: bool,
+    /// Function name -> inclusive time
+    times: HashMap<String, Duration>,
+    /// Functions entered since timing started
+    stack: Vec<ProfiledCall>,
+    /// Total time spent in debug hook: halted or handling event, excluded from function times
+    paused: Duration,
+    /// Folded call stack `a;b;c` -> microseconds spent in its last function, not in callees
+    folded: HashMap<String, u64>,
+    /// Record lines reached
+    pub covering: bool,
+    /// Interned source names, index is source id
+    sources: Vec<String>,
+    /// Source name -> source id
+    source_ids: HashMap<String, usize>,
+    /// Reached (source id, line) pairs
+    covered: HashSet<(usize, isize)>,
+}
+
+impl Profiler {
+    /// Account debug event, that occured at time `at`
+    fn record(&mut self, event: &DebugEventWithSrc, at: Instant) {
+        if let (true, DebugEvent::FnCall(name, _)) = (self.counting, &event.event) {
+            *self.calls.entry(name.clone()).or_default() += 1;
+        }
+
+        if let (true, DebugEvent::Line(line), Some(src)) = (self.covering, &event.event, &event.src) {
+            let id = match self.source_ids.get(src) {
+                Some(id) => *id,
+                None => {
+                    self.sources.push(src.clone());
+                    self.source_ids.insert(src.clone(), self.sources.len() - 1);
+                    self.sources.len() - 1
+                }
+            };
+            self.covered.insert((id, *line));
+        }
+
+        if !self.timing {
+            return;
+        }
+
+        match &event.event {
+            DebugEvent::FnCall(name, _) => self.stack.push(ProfiledCall {
+                name: name.clone(),
+                started: at,
+                paused: self.paused,
+                callees: Duration::ZERO,
+            }),
+            DebugEvent::FnRet(name, _) => {
+                // Returns of functions entered before timing started are skipped,
+                // unmatched calls above returned one are dropped
+                let Some(pos) = self.stack.iter().rposition(|c| c.name == *name) else {
+                    return;
+                };
+                self.stack.truncate(pos + 1);
+                let call = self.stack.pop().unwrap();
+
+                let elapsed = at.duration_since(call.started).saturating_sub(self.paused - call.paused);
+
+                let folded = self.stack.iter()
+                    .map(|c| c.name.as_str())
+                    .chain(std::iter::once(call.name.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                *self.folded.entry(folded).or_default() += elapsed.saturating_sub(call.callees).as_micros() as u64;
+                if let Some(caller) = self.stack.last_mut() {
+                    caller.callees += elapsed;
+                }
+
+                // Recursive calls are accounted once, by outermost one
+                if !self.stack.iter().any(|c| c.name == call.name) {
+                    *self.times.entry(call.name).or_default() += elapsed;
+                }
+            }
+            DebugEvent::Line(_) => (),
+        }
+    }
+
+    /// Exclude time spent in debug hook from function times
+    fn pause(&mut self, time: Duration) {
+        if self.timing {
+            self.paused += time;
+        }
+    }
+
+    /// Start or stop measuring time spent in functions.
+    /// Functions entered before start are not measured
+    pub fn set_timing(&mut self, active: bool) {
+        self.timing = active;
+        self.stack.clear();
+    }
+
+    /// Inclusive times of functions, slowest first
+    pub fn function_times(&self) -> Vec<(&str, Duration)> {
+        let mut times: Vec<_> = self.times.iter()
+            .map(|(name, time)| (name.as_str(), *time))
+            .collect();
+        times.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+        times
+    }
+
+    /// Counts of function calls, most called first
+    pub fn call_counts(&self) -> Vec<(&str, u64)> {
+        let mut counts: Vec<_> = self.calls.iter()
+            .map(|(name, count)| (name.as_str(), *count))
+            .collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
+
+    /// Measured call stacks in folded format of flamegraph tools: 
+    /// `a;b;c <microseconds>` per line, weighted by time spent in last function
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<_> = self.folded.iter().collect();
+        stacks.sort();
+        stacks.into_iter()
+            .map(|(stack, micros)| format!("{stack} {micros}\n"))
+            .collect()
+    }
+
+    /// Covered lines of each source, sorted
+    pub fn covered_lines(&self) -> BTreeMap<&str, Vec<isize>> {
+        let mut lines: BTreeMap<&str, Vec<isize>> = BTreeMap::new();
+        for (id, line) in &self.covered {
+            lines.entry(self.sources[*id].as_str()).or_default().push(*line);
+        }
+        lines.values_mut().for_each(|l| l.sort_unstable());
+        lines
+    }
+
+    /// Clear call counts, function times, folded stacks and covered lines
+    pub fn reset(&mut self) {
+        self.calls.clear();
+        self.times.clear();
+        self.folded.clear();
+        self.covered.clear();
+        self.sources.clear();
+        self.source_ids.clear();
+    }
+}
+
+/// Depth of locals expansion in snapshots taken on breakpoint hit
+pub const BP_SNAPSHOT_DEPTH: usize = 4;
+
+/// Debug event sent to frontend with data collected by hook
+#[derive(Clone, Debug)]
+pub struct DebugEventBundle {
+    pub event: DebugEventWithSrc,
+    /// Breakpoint, that halted execution
+    pub bp: Option<SqBreakpoint>,
+    /// Call arguments, if requested during tracing
+    pub args: Option<Vec<SqLocalVar>>,
+    /// Message of hit logpoint
+    pub log: Option<String>,
+    /// Number of hit breakpoint with snapshot option and locals of hit function
+    pub snapshot: Option<(u32, Vec<SqLocalVar>)>,
+    /// Number of breakpoint, which condition failed to evaluate, and error.
+    /// Failed condition is treated as true
+    pub cond_error: Option<(u32, String)>,
+    /// Vm is halted at this event and waits for commands, e.g. after step
+    pub halted: bool,
+    /// Event passed trace filter. Events neither halted nor traced are sent only for message or snapshot
+    pub traced: bool,
+    /// Time of hook call, before it was blocked by sending
+    pub time: Instant,
+}
 
 type SafeVm = Vm<safety::Safe>;
 /// SQ Debugger middleware (backend is debug hook closure)
 pub struct SqDebugger{
     /// State of execution, shared with hook
-    exec_state: Arc<Atomic<ExecState>>,
+    exec_state: Arc<ExecStateCell>,
 
     /// Channel for sending commands to hook
     sender: Sender<DebugMsg>,
@@ -214,35 +811,165 @@ pub struct SqDebugger{
     /// Channel only for receiving events
     event_receiver: Receiver<DebugEventBundle>,
 
+    /// Additional event receivers, shared with hook
+    subscribers: Arc<Mutex<Vec<Sender<DebugEventWithSrc>>>>,
+
     /// Breakpont store, shared with hook
     breakpoints: Arc<Mutex<BreakpointStore>>,
 
-    /// VM being debugged
-    vm: SafeVm,
+    /// Watchpoint store, shared with hook
+    watchpoints: Arc<Mutex<WatchpointStore>>,
+
+    /// Function statistics, shared with hook
+    profiler: Arc<Mutex<Profiler>>,
+
+    /// Last thrown errors, shared with error handler
+    errors: Arc<Mutex<SqErrorLog>>,
+
+    /// Halt on thrown errors, shared with error handler
+    catch_errors: Arc<AtomicBool>,
+
+    /// Vm was halted by error handler, but it was not taken yet
+    error_halt: Arc<AtomicBool>,
+
+    /// Count of debug hook calls, shared with hook
+    event_count: Arc<AtomicU64>,
+
+    /// Nanoseconds spent in hook calls that did not halt vm, shared with hook
+    hook_nanos: Arc<AtomicU64>,
+
+    /// Count of hook calls that did not halt vm, shared with hook
+    hook_calls: Arc<AtomicU64>,
+
+    /// Time of attach or last uptime reset
+    counting_since: Mutex<Instant>,
+
+    /// Timeout of waiting for hook response
+    timeout: Atomic<Duration>,
+
+    /// Count of messages sent to hook
+    msgs_sent: AtomicU64,
+
+    /// Count of messages taken by hook, shared with hook
+    msgs_handled: Arc<AtomicU64>,
+
+    /// Frontend is waiting for hook response
+    awaiting_resp: AtomicBool,
+
+    /// Call stack depth to halt at once reached, 0 if disabled. Shared with hook
+    halt_depth: Arc<AtomicUsize>,
+
+    /// Number of debugger in [ATTACHED_VMS]
+    id: usize,
+
+    /// VM being debugged, taken on detach
+    vm: Option<SafeVm>,
 }
 
 impl SqDebugger
 {
+    /// Check if debugger is already attached to this VM
+    pub fn is_attached<S: safety::VmDrop>(vm: &Vm<S>) -> bool {
+        let handle = vm.api().handle() as usize;
+        ATTACHED_VMS.lock().unwrap().iter().any(|a| a.handle == handle)
+    }
+
     /// Attach debugger to SQVM through setting debug hook.
+    ///
+    /// If other debugger is already attached, its hook will be overwritten.
+    /// Use [SqDebugger::is_attached] to check it beforehand.
+    ///
+    /// Hook set by someone else, e.g. host application, is kept and called before debugger's one
     pub fn attach(vm: SafeVm) -> SqDebugger {
+        let handle = vm.api().handle() as usize;
+        let id = NEXT_DEBUGGER_ID.fetch_add(1, Ordering::Relaxed);
+        let foreign_hook = {
+            let mut attached = ATTACHED_VMS.lock().unwrap();
+            match attached.iter_mut().find(|a| a.handle == handle) {
+                Some(entry) => {
+                    warn!("debugger is already attached to vm {handle:X}, overwriting debug hook");
+                    entry.debugger = id;
+                    entry.foreign_hook.clone()
+                }
+                None => {
+                    let foreign_hook = vm.get_debug_hook()
+                        .unwrap_or_else(|e| {
+                            warn!("failed to get debug hook of vm {handle:X}, it will be overwritten: {e}");
+                            None
+                        })
+                        .map(Arc::new);
+                    attached.push(AttachedVm { handle, debugger: id, foreign_hook: foreign_hook.clone() });
+                    foreign_hook
+                }
+            }
+        };
 
         let (tx, rx) = unbounded();
         let (resp_tx, resp_rx) = bounded(0);
         let (event_tx, event_rx) = bounded(0);
 
         let mut dbg = Self {
-            exec_state: Arc::new(Atomic::new(ExecState::Halted)),
+            exec_state: Arc::new(ExecStateCell::new(ExecState::Halted)),
             sender: tx,
             receiver: resp_rx,
             event_receiver: event_rx,
+            subscribers: Arc::new(Mutex::new(vec![])),
             breakpoints: Arc::new(Mutex::new(BreakpointStore::new())),
-            vm,
+            watchpoints: Arc::new(Mutex::new(WatchpointStore::new())),
+            profiler: Arc::new(Mutex::new(Profiler::default())),
+            errors: Arc::new(Mutex::new(SqErrorLog::with_capacity(ERROR_LOG_SIZE))),
+            catch_errors: Arc::new(AtomicBool::new(false)),
+            error_halt: Arc::new(AtomicBool::new(false)),
+            event_count: Arc::new(AtomicU64::new(0)),
+            hook_nanos: Arc::new(AtomicU64::new(0)),
+            hook_calls: Arc::new(AtomicU64::new(0)),
+            counting_since: Mutex::new(Instant::now()),
+            timeout: Atomic::new(RECV_TIMEOUT),
+            msgs_sent: AtomicU64::new(0),
+            msgs_handled: Arc::new(AtomicU64::new(0)),
+            awaiting_resp: AtomicBool::new(false),
+            halt_depth: Arc::new(AtomicUsize::new(0)),
+            id,
+            vm: Some(vm),
         };
+        let vm = dbg.vm.as_mut().unwrap();
+
+        // Record errors, halting on next debug event if requested.
+        // Vm calls handler only for errors not caught by script
+        let errors = dbg.errors.clone();
+        let catch_errors = dbg.catch_errors.clone();
+        let error_halt = dbg.error_halt.clone();
+        let exec_state = dbg.exec_state.clone();
+        vm.set_error_handler(move |error, vm| {
+            let mut errors = errors.lock().unwrap();
+            if errors.len() == ERROR_LOG_SIZE {
+                errors.pop_front();
+            }
+            errors.push_back(SqThrownError { error, backtrace: collect_backtrace(vm) });
+
+            if catch_errors.load(Ordering::Relaxed) {
+                error_halt.store(true, Ordering::Relaxed);
+                exec_state.store(ExecState::Halted);
+            }
+        });
 
         let exec_state = dbg.exec_state.clone();
         let breakpoints = dbg.breakpoints.clone();
+        let watchpoints = dbg.watchpoints.clone();
+        let profiler = dbg.profiler.clone();
+        let event_count = dbg.event_count.clone();
+        let hook_nanos = dbg.hook_nanos.clone();
+        let hook_calls = dbg.hook_calls.clone();
+        let msgs_handled = dbg.msgs_handled.clone();
+        let halt_depth = dbg.halt_depth.clone();
+        let subscribers = dbg.subscribers.clone();
+        let detach_hook = foreign_hook.clone();
         let mut debugging = true;
         let mut tracing = false;
+        let mut trace_args = false;
+        let mut trace_filter: Option<TraceFilter> = None;
+        // Call stack size to halt at, set by step over and step out
+        let mut step_depth = None;
 
         // TODO: Somehow track threads this function being called from.
         // Currently it`s possible, that after step closure will be called from another thread,
@@ -251,32 +978,121 @@ impl SqDebugger
         // only in thread, where this step was made.
         
         // Attached debugger will receive messages and respond to them
-        dbg.vm.set_debug_hook(move |e, vm| {
+        vm.set_chained_debug_hook(foreign_hook, move |e, vm| {
+            event_count.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
             
             // if debugging disabled during hook call
             if !debugging {
                 return;
             }
 
-            let bp = breakpoints.lock().unwrap()
-                .match_event(&e)
-                .cloned();
+            profiler.lock().unwrap().record(&e, started);
+
+            // Subscribers get every event, not only traced ones. Channels are unbounded, send never blocks
+            subscribers.lock().unwrap().retain(|tx| tx.send(e.clone()).is_ok());
+
+            // Conditions are evaluated without lock, as frontend may wait for it
+            let candidates = breakpoints.lock().unwrap().match_conditions(&e);
+            let mut cond_error = None;
+            let mut bp = None;
+            for (number, condition) in candidates {
+                if let Some(condition) = condition {
+                    // Hook is called during condition evaluation too
+                    #[allow(unused_assignments)] {
+                        debugging = false;
+                    }
+                    let res = eval_condition(vm, &condition);
+                    debugging = true;
+
+                    match res {
+                        Ok(false) => continue,
+                        Ok(true) => (),
+                        Err(err) => cond_error = Some((number, err.to_string())),
+                    }
+                }
+
+                bp = breakpoints.lock().unwrap().hit(number);
+                if bp.is_some() {
+                    break;
+                }
+            }
+
+            // Locals are captured in hit function, also by logpoints
+            let snapshot = bp.as_ref()
+                .filter(|bp| bp.snapshot)
+                .map(|bp| (bp.number, vm.get_frame_locals(1, Some(BP_SNAPSHOT_DEPTH))));
+
+            // Logpoints do not halt, only message is sent
+            let (bp, log) = match bp {
+                Some(SqBreakpoint { log: Some(template), .. }) => {
+                    #[allow(unused_assignments)] {
+                        debugging = false;
+                    }
+                    let msg = format_log(vm, &template);
+                    debugging = true;
+                    (None, Some(msg))
+                }
+                bp => (bp, None),
+            };
+
+            // One-shot depth trigger. Level 0 is hook itself
+            let depth = halt_depth.load(Ordering::Relaxed);
+            let depth_reached = depth != 0
+                && matches!(e.event, DebugEvent::FnCall(..))
+                && (vm.api().call_stack_len() as usize).saturating_sub(1) >= depth;
+            if depth_reached {
+                halt_depth.store(0, Ordering::Relaxed);
+            }
+
+            let watch_changed = watchpoints.lock().unwrap().check(vm);
 
+            // Step over or out is finished, when vm returned to target or outer function
+            let stepped = matches!(step_depth, 
+                Some(d) if vm.api().call_stack_len() as usize <= d
+            );
 
             // If VM was running and ran into breakpoint, halt it 
-            let state = if bp.is_some() {
+            let state = if bp.is_some() || depth_reached || stepped || watch_changed {
                 tracing = false;
-                exec_state.store(ExecState::Halted, Ordering::Relaxed);
+                exec_state.store(ExecState::Halted);
                 ExecState::Halted
             } else {
-                exec_state.load(Ordering::Relaxed)
+                exec_state.load()
             };
     
+            // Breakpoint or halt request ends step over or out early
+            if state == ExecState::Halted {
+                step_depth = None;
+            }
+
+            // Time of calls that wait for frontend is not an overhead
+            let timed = !tracing && state == ExecState::Running;
+
             // If tracing active, or vm ran into brakpoint, or 
             // step cmd was received on previous debug hook call,
             // send debug event back with optional breakpoint
-            if tracing || state == ExecState::Halted {
-                event_tx.send((e, bp)).unwrap();
+            let traced = tracing && trace_filter.as_ref().is_none_or(|f| f(&e));
+            if traced || state == ExecState::Halted || log.is_some() || snapshot.is_some() || cond_error.is_some() {
+                // At call event only `this` and arguments are alive
+                let args = if traced && trace_args && matches!(e.event, DebugEvent::FnCall(..)) {
+                    let mut args = vec![];
+                    let mut idx = 0;
+                    while let Ok(Some(loc)) = vm.get_local(1, idx, Some(0)) {
+                        if loc.name != "this" {
+                            args.push(loc);
+                        }
+                        idx += 1;
+                    }
+                    Some(args)
+                } else {
+                    None
+                };
+
+                let halted = state == ExecState::Halted;
+                event_tx.send(DebugEventBundle { 
+                    event: e, bp, args, log, snapshot, cond_error, halted, traced, time: started 
+                }).unwrap();
 
                 // Stop tracing, if vm was halted 
                 if state == ExecState::Halted {
@@ -285,27 +1101,32 @@ impl SqDebugger
             }
 
             loop {
-                if let Ok(msg) = rx.try_recv() { match msg {
+                if let Ok(msg) = rx.try_recv() {
+                    msgs_handled.fetch_add(1, Ordering::Relaxed);
+                    match msg {
                     // Expected immediate receive on other end for all sending cmds
 
                     DebugMsg::Step => break,
+                    DebugMsg::StepOver => {
+                        step_depth = Some(vm.api().call_stack_len() as usize);
+                        exec_state.store(ExecState::Running);
+                        break;
+                    }
+                    DebugMsg::StepOut => {
+                        // Halt in caller, after current function returned
+                        step_depth = Some((vm.api().call_stack_len() as usize).saturating_sub(1));
+                        exec_state.store(ExecState::Running);
+                        break;
+                    }
                     DebugMsg::Backtrace => {
-                        let mut bt = vec![];
-
-                        let stack_size = vm.api().call_stack_len() as usize;
-
-                        for lvl in 1..stack_size {
-                            if let Ok(info) = vm.get_stack_info(lvl) {
-                                bt.push(info);
-                            }
-                        }
-
-                        resp_tx.send(DebugResp::Backtrace(bt)).unwrap();
+                        resp_tx.send(DebugResp::Backtrace(collect_backtrace(vm))).unwrap();
                     },
 
-                    DebugMsg::Trace => {
+                    DebugMsg::Trace(args, filter) => {
                         tracing = true;
-                        exec_state.store(ExecState::Running, Ordering::Relaxed);
+                        trace_args = args;
+                        trace_filter = filter;
+                        exec_state.store(ExecState::Running);
                         break;
                     }
 
@@ -313,30 +1134,27 @@ impl SqDebugger
                         let stack_size = vm.api().call_stack_len() as usize;
 
                         // Store all locals if level isn`t specified  
-                        let lvl = if let Some(lvl) = lvl_opt { lvl } else { 1 };
+                        let levels = match lvl_opt {
+                            Some(lvl) => lvl..lvl + 1,
+                            None => 1..stack_size,
+                        };
 
-                        if lvl >= stack_size || lvl < 1 {
+                        if levels.start >= stack_size || levels.start < 1 {
                             resp_tx.send(DebugResp::Locals(None)).unwrap();
                             break 'locals;
                         }
 
                         let mut v = vec![];
 
-                        for lvl in lvl..stack_size {
-                            let mut idx = 0;
-                            while let Ok(Some(loc)) = vm.get_local(lvl, idx, Some(depth)) {
-                                v.push(SqLocalVarWithLvl { var: loc, lvl });
-                                idx += 1;
-                            }
-
-                            if lvl_opt.is_some() {
-                                break;
-                            }
+                        for lvl in levels {
+                            v.extend(vm.get_frame_locals(lvl, Some(depth))
+                                .into_iter()
+                                .map(|var| SqLocalVarWithLvl { var, lvl }));
                         }
 
                         resp_tx.send(DebugResp::Locals(if v.is_empty() { None } else { Some(v) })).unwrap();
                     },
-                    DebugMsg::Eval(SqScriptDesc { capture, script, depth, debug }) => 'eval: {
+                    DebugMsg::Eval(SqScriptDesc { capture, values, script, depth, debug }) => 'eval: {
                         let mut env = IndexMap::with_capacity(capture.len());
 
                         // Gather capture variables
@@ -383,6 +1201,13 @@ impl SqDebugger
 
                             vm.push(env)?;
 
+                            for (name, val) in values {
+                                vm.push(name.as_str());
+                                vm.push(val)?;
+                                vm.new_slot(-3, false)
+                                    .map_err(|e| e.into_stack_error("failed to set eval value"))?;
+                            }
+
                             // Currently I don't know what's going here.
                             // But seems like `env` becomes implicitly extended with root table.
                             // And if these lines uncommented, root table overrrides `env`
@@ -401,28 +1226,150 @@ impl SqDebugger
                         debugging = true;
 
                         // Halt after evaluation
-                        exec_state.store(ExecState::Halted, Ordering::Relaxed);
+                        exec_state.store(ExecState::Halted);
+                    }
+
+                    DebugMsg::FrameClosure(lvl) => {
+                        let res = vm.get_frame_closure(lvl).map_err(|e| e.into());
+                        resp_tx.send(DebugResp::FrameClosure(res)).unwrap();
+                    }
+
+                    DebugMsg::FunctionProtos => {
+                        let res = vm.get_function_protos().map_err(|e| e.into());
+                        resp_tx.send(DebugResp::FunctionProtos(res)).unwrap();
+                    }
+
+                    DebugMsg::RawLocal(lvl, path) => {
+                        let res = vm.get_local_raw(lvl, &path).map_err(|e| e.into());
+                        resp_tx.send(DebugResp::RawLocal(res)).unwrap();
+                    }
+
+                    DebugMsg::AddWatchpoint(lvl, path) => {
+                        let res = watchpoints.lock().unwrap().add(vm, lvl, path);
+                        resp_tx.send(DebugResp::AddWatchpoint(res)).unwrap();
+                    }
+
+                    DebugMsg::SetLocal(lvl, path, value) => {
+                        let res = vm.set_local_path(lvl, &path, value).map_err(|e| e.into());
+                        resp_tx.send(DebugResp::SetLocal(res)).unwrap();
+                    }
+
+                    DebugMsg::LocalChild { lvl, path, depth, upvalues } => {
+                        let get_path = || match lvl {
+                            Some(lvl) => vm.get_local_path(lvl, &path, Some(depth)),
+                            None => {
+                                let stack_size = vm.api().call_stack_len() as usize;
+                                let mut res = vm.get_local_path(1, &path, Some(depth));
+                                for lvl in 2..stack_size {
+                                    if res.is_ok() {
+                                        break;
+                                    }
+                                    if let ok @ Ok(_) = vm.get_local_path(lvl, &path, Some(depth)) {
+                                        res = ok;
+                                    }
+                                }
+                                res
+                            }
+                        };
+                        let res = if upvalues { vm.with_upvalues(get_path) } else { get_path() };
+                        resp_tx.send(DebugResp::LocalChild(res.map_err(|e| e.into()))).unwrap();
+                    }
+
+                    DebugMsg::GlobalChild { path, depth } => {
+                        let res = vm.get_global_path(&path, Some(depth)).map_err(|e| e.into());
+                        resp_tx.send(DebugResp::GlobalChild(res)).unwrap();
+                    }
+
+                    DebugMsg::RootTable(depth) => {
+                        vm.push_root_table();
+                        let res: SqDebugResult<DynSqVar> = vm.get_constrain(-1, Some(depth))
+                            .map_err(|e| e.into());
+                        vm.pop(1);
+                        resp_tx.send(DebugResp::RootTable(res)).unwrap();
+                    }
+
+                    DebugMsg::DebugInfo(enable) => vm.api().enable_debug_info(enable),
+
+                    // Closure of this hook is kept alive by vm until call returns
+                    DebugMsg::Detach => {
+                        let mut vm = unsafe { Vm::from_handle(vm.api().handle()).into_friend() };
+                        match &detach_hook {
+                            Some(hook) => vm.restore_debug_hook(hook),
+                            None => vm.remove_debug_hook(),
+                        }
+                        vm.remove_error_handler();
+                        tracing = false;
+                        exec_state.store(ExecState::Running);
+                        break;
                     }
                 }}
 
-                if exec_state.load(Ordering::Relaxed) == ExecState::Running {
+                if exec_state.load() == ExecState::Running {
                     break;
                 }
-                std::thread::sleep(Duration::from_millis(50));
+                // Sleep until resumed or new message is sent
+                exec_state.wait_halted(|| rx.is_empty());
+            }
+
+            profiler.lock().unwrap().pause(started.elapsed());
+
+            if timed {
+                hook_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                hook_calls.fetch_add(1, Ordering::Relaxed);
             }
         });
 
         dbg
     }
 
-    /// Continue execution, but send every debug event
-    pub fn start_tracing(&self) {
-        self.sender.send(DebugMsg::Trace).unwrap();
+    /// Send message to hook
+    fn send(&self, msg: DebugMsg) {
+        self.msgs_sent.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(msg).unwrap();
+        self.exec_state.notify();
+    }
+
+    /// Wait for hook response until timeout
+    fn recv(&self) -> Result<DebugResp, RecvTimeoutError> {
+        self.awaiting_resp.store(true, Ordering::Relaxed);
+        let resp = self.receiver.recv_timeout(self.timeout());
+        self.awaiting_resp.store(false, Ordering::Relaxed);
+        resp
+    }
+
+    /// Get count of messages not yet taken by hook and whether response is awaited.
+    ///
+    /// Pending messages while vm is halted and no response is awaited mean desync
+    pub fn queue_state(&self) -> (u64, bool) {
+        let pending = self.msgs_sent.load(Ordering::Relaxed)
+            .saturating_sub(self.msgs_handled.load(Ordering::Relaxed));
+        (pending, self.awaiting_resp.load(Ordering::Relaxed))
+    }
+
+    /// Halt once, when call stack depth reaches `depth` on function call.
+    /// `None` disables pending trigger
+    pub fn set_halt_depth(&self, depth: Option<usize>) {
+        self.halt_depth.store(depth.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Get call stack depth to halt at, if trigger is not fired yet
+    pub fn halt_depth(&self) -> Option<usize> {
+        match self.halt_depth.load(Ordering::Relaxed) {
+            0 => None,
+            depth => Some(depth),
+        }
+    }
+
+    /// Continue execution, but send every debug event.
+    ///
+    /// If `args` is true, call events are sent with callee arguments
+    pub fn start_tracing(&self, args: bool, filter: Option<TraceFilter>) {
+        self.send(DebugMsg::Trace(args, filter));
     }
 
     /// Resume execution
     pub fn resume(&self) {
-        self.exec_state.store(ExecState::Running, Ordering::Relaxed);
+        self.exec_state.store(ExecState::Running);
     }
 
     /// Get event receiver
@@ -430,8 +1377,20 @@ impl SqDebugger
         &self.event_receiver
     }
 
+    /// Get additional receiver of every debug event of vm, 
+    /// unlike [SqDebugger::event_rx], which gets only traced and halting ones.
+    /// Events of scripts evaluated by debugger itself are not sent.
+    ///
+    /// Events are queued without limit until received.
+    /// Dropped receivers are pruned lazily, on the next sent event
+    pub fn subscribe(&self) -> Receiver<DebugEventWithSrc> {
+        let (tx, rx) = unbounded();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
     /// Get breakpoint store
-    pub fn breakpoints(&self) -> MutexGuard<BreakpointStore> {
+    pub fn breakpoints(&self) -> MutexGuard<'_, BreakpointStore> {
        self.breakpoints.lock().unwrap()
     }
 
@@ -440,14 +1399,50 @@ impl SqDebugger
         *self.breakpoints.lock().unwrap() = points; 
     }
 
+    /// Get last thrown errors, oldest first
+    pub fn errors(&self) -> MutexGuard<'_, SqErrorLog> {
+        self.errors.lock().unwrap()
+    }
+
+    /// Halt on errors not caught by script.
+    ///
+    /// Handler can not block vm, so it is halted on the next debug event,
+    /// which is usually in the caller. Backtrace is recorded at the throw site
+    pub fn set_catch_errors(&self, enable: bool) {
+        self.catch_errors.store(enable, Ordering::Relaxed);
+    }
+
+    /// Get error that halted vm, if it was not taken yet
+    pub fn take_error_halt(&self) -> Option<SqThrownError> {
+        if !self.error_halt.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        self.errors().back().cloned()
+    }
+
     /// Halt execution by blocking vm on debug hook call
     pub fn halt(&self) {
-        self.exec_state.store(ExecState::Halted, Ordering::Relaxed);
+        self.exec_state.store(ExecState::Halted);
     }
 
     /// Unlock current debug hook call
     pub fn step(&self) {
-        self.sender.send(DebugMsg::Step).unwrap();
+        self.send(DebugMsg::Step);
+    }
+
+    /// Resume until vm returns to current call stack depth, 
+    /// so called functions are executed without halting.
+    ///
+    /// Breakpoints and [SqDebugger::halt] stop it early
+    pub fn step_over(&self) {
+        self.send(DebugMsg::StepOver);
+    }
+
+    /// Resume until current function returns, halting on the next event in caller.
+    ///
+    /// Breakpoints and [SqDebugger::halt] stop it early
+    pub fn step_out(&self) {
+        self.send(DebugMsg::StepOut);
     }
 
     /// Get local variables and their values at specified level.
@@ -467,9 +1462,9 @@ impl SqDebugger
         lvl: Option<usize>,
         depth: usize
     ) -> SqDebugResult<Vec<SqLocalVarWithLvl>> {
-        self.sender.send(DebugMsg::Locals(lvl, depth)).unwrap();
+        self.send(DebugMsg::Locals(lvl, depth));
         
-        match self.receiver.recv_timeout(RECV_TIMEOUT) {
+        match self.recv() {
             Ok(DebugResp::Locals(Some(loc))) => Ok(loc),
             Ok(DebugResp::Locals(None)) => Err(SqDebugError::NoLocals { 
                 all_levels: lvl.is_none()
@@ -494,11 +1489,28 @@ impl SqDebugger
         capture_locals: Vec<SqCaptureLocal>,
         depth: usize
     ) -> SqDebugResult<DynSqVar> {
-        self.sender.send(DebugMsg::Eval(SqScriptDesc {
-            capture: capture_locals, script, depth, debug: false
-        })).unwrap();
+        self.execute_with_values(script, capture_locals, IndexMap::new(), depth)
+    }
+
+    /// Compile and execute arbitrary squirrel script, passing `values` to compiled closure.
+    ///
+    /// Values are pushed as copies, so containers lose identity with original objects,
+    /// and must contain only types that can be pushed (no instances, closures, etc.).
+    /// Values override captured locals with the same name.
+    ///
+    /// See [SqDebugger::execute] for other args
+    pub fn execute_with_values(
+        &self,
+        script: String,
+        capture_locals: Vec<SqCaptureLocal>,
+        values: IndexMap<String, DynSqVar>,
+        depth: usize
+    ) -> SqDebugResult<DynSqVar> {
+        self.send(DebugMsg::Eval(SqScriptDesc {
+            capture: capture_locals, values, script, depth, debug: false
+        }));
 
-        match self.receiver.recv_timeout(RECV_TIMEOUT) {
+        match self.recv() {
             Ok(DebugResp::EvalResult(res)) => res,
             Ok(r) => Err(SqDebugError::InvalidMessage { 
                 expected: "EvalResult",
@@ -522,9 +1534,9 @@ impl SqDebugger
         capture_locals: Vec<SqCaptureLocal>,
         depth: usize
     ) -> impl Fn() -> SqDebugResult<DynSqVar> {
-        self.sender.send(DebugMsg::Eval(SqScriptDesc {
-            capture: capture_locals, script, depth, debug: true
-        })).unwrap();
+        self.send(DebugMsg::Eval(SqScriptDesc {
+            capture: capture_locals, values: IndexMap::new(), script, depth, debug: true
+        }));
        
         let receiver = self.receiver.clone();
 
@@ -544,9 +1556,9 @@ impl SqDebugger
     /// current_fn -> root
     /// ```
     pub fn get_backtrace(&self) -> SqDebugResult<SqBacktrace> {
-        self.sender.send(DebugMsg::Backtrace).unwrap();
+        self.send(DebugMsg::Backtrace);
         
-        match self.receiver.recv_timeout(RECV_TIMEOUT) {
+        match self.recv() {
             Ok(DebugResp::Backtrace(bt)) => Ok(bt),
             Ok(r) => Err(SqDebugError::InvalidMessage {
                 expected: "Backtrace",
@@ -556,7 +1568,230 @@ impl SqDebugger
         }
     }
 
+    /// Get local variable or its child at call stack level, 
+    /// expanding only target to `depth`.
+    ///
+    /// `path` starts with local name, following are keys in containers.
+    /// If level is not specified, first level where path matches is used.
+    /// Variables captured by closures are fetched only if `upvalues` is set
+    pub fn get_local_child(
+        &self,
+        lvl: Option<usize>,
+        path: Vec<SqPathKey>,
+        depth: usize,
+        upvalues: bool
+    ) -> SqDebugResult<DynSqVar> {
+        self.send(DebugMsg::LocalChild { lvl, path, depth, upvalues });
+
+        match self.recv() {
+            Ok(DebugResp::LocalChild(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "LocalChild",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get global variable or its child, expanding only target to `depth`.
+    ///
+    /// `path` contains keys of root table and its children
+    pub fn get_global_child(&self, path: Vec<SqPathKey>, depth: usize) -> SqDebugResult<DynSqVar> {
+        self.send(DebugMsg::GlobalChild { path, depth });
+
+        match self.recv() {
+            Ok(DebugResp::GlobalChild(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "GlobalChild",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Halt when value of local or its child at call stack level changes.
+    ///
+    /// Watchpoint is removed, when function of local returns
+    pub fn add_watchpoint(&self, lvl: usize, path: Vec<SqPathKey>) -> SqDebugResult<u32> {
+        self.send(DebugMsg::AddWatchpoint(lvl, path));
+
+        match self.recv() {
+            Ok(DebugResp::AddWatchpoint(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "AddWatchpoint",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get watchpoint store
+    pub fn watchpoints(&self) -> MutexGuard<'_, WatchpointStore> {
+        self.watchpoints.lock().unwrap()
+    }
+
+    /// Get function statistics
+    pub fn profiler(&self) -> MutexGuard<'_, Profiler> {
+        self.profiler.lock().unwrap()
+    }
+
+    /// Set scalar member of container local at call stack level.
+    ///
+    /// `path` starts with local name, following are keys in containers
+    pub fn set_local(&self, lvl: usize, path: Vec<SqPathKey>, value: DynSqVar) -> SqDebugResult<()> {
+        self.send(DebugMsg::SetLocal(lvl, path, value));
+
+        match self.recv() {
+            Ok(DebugResp::SetLocal(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "SetLocal",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get internal representation of local variable or its child at call stack level.
+    ///
+    /// `path` starts with local name, following are keys in containers
+    pub fn get_local_raw(&self, lvl: usize, path: Vec<SqPathKey>) -> SqDebugResult<SqRawObject> {
+        self.send(DebugMsg::RawLocal(lvl, path));
+
+        match self.recv() {
+            Ok(DebugResp::RawLocal(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "RawLocal",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get info of closure executed at specified call stack level.
+    ///
+    /// Returns [DynSqVar::Closure] or [DynSqVar::NativeClosure]
+    pub fn get_frame_closure(&self, lvl: usize) -> SqDebugResult<DynSqVar> {
+        self.send(DebugMsg::FrameClosure(lvl));
+
+        match self.recv() {
+            Ok(DebugResp::FrameClosure(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "FrameClosure",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get timeout of waiting for hook response
+    pub fn timeout(&self) -> Duration {
+        self.timeout.load(Ordering::Relaxed)
+    }
+
+    /// Set timeout of waiting for hook response
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.timeout.store(timeout, Ordering::Relaxed);
+    }
+
+    /// Get time passed and count of debug events (line, call and return) 
+    /// received since attach or last reset
+    pub fn uptime(&self) -> (Duration, u64) {
+        let since = *self.counting_since.lock().unwrap();
+        (since.elapsed(), self.event_count.load(Ordering::Relaxed))
+    }
+
+    /// Get total time spent in debug hook calls that did not halt vm, and count of such calls
+    pub fn hook_stats(&self) -> (Duration, u64) {
+        (
+            Duration::from_nanos(self.hook_nanos.load(Ordering::Relaxed)),
+            self.hook_calls.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Restart uptime and debug events counting
+    pub fn reset_uptime(&self) {
+        *self.counting_since.lock().unwrap() = Instant::now();
+        self.event_count.store(0, Ordering::Relaxed);
+    }
+
+    /// Get root table (globals) of vm.
+    ///
+    /// `depth` - depth of eager containers expansion, see [SqDebugger::get_locals]
+    pub fn get_root_table(&self, depth: usize) -> SqDebugResult<DynSqVar> {
+        self.send(DebugMsg::RootTable(depth));
+
+        match self.recv() {
+            Ok(DebugResp::RootTable(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "RootTable",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Get compiled functions of closures reachable from root table, 
+    /// including functions declared inside of them
+    pub fn get_function_protos(&self) -> SqDebugResult<Vec<SqFunctionProto>> {
+        self.send(DebugMsg::FunctionProtos);
+
+        match self.recv() {
+            Ok(DebugResp::FunctionProtos(res)) => res,
+            Ok(r) => Err(SqDebugError::InvalidMessage {
+                expected: "FunctionProtos",
+                received: r.variant_name(),
+            }),
+            Err(_) => Err(SqDebugError::Timeout)
+        }
+    }
+
+    /// Enable or disable debug info generation for scripts compiled from now on.
+    ///
+    /// Applied on the next debug event. Already compiled closures keep
+    /// their line info, so breakpoints in them may still not be reached
+    pub fn set_debug_info(&self, enable: bool) {
+        self.send(DebugMsg::DebugInfo(enable));
+    }
+
     pub fn exec_state(&self) -> ExecState {
-        self.exec_state.load(Ordering::Relaxed)
+        self.exec_state.load()
+    }
+
+    /// Remove debug hook and error handler from vm and resume it.
+    ///
+    /// Hook is removed on its next call. Debugger receives nothing afterwards,
+    /// but another one can be attached to the same vm
+    pub fn detach_hooks(&mut self) {
+        self.send(DebugMsg::Detach);
+        self.resume();
+        self.unregister();
+    }
+
+    /// Remove vm from [ATTACHED_VMS], unless other debugger was attached to it later
+    fn unregister(&self) {
+        if let Some(vm) = &self.vm {
+            let handle = vm.api().handle() as usize;
+            ATTACHED_VMS.lock().unwrap().retain(|a| a.handle != handle || a.debugger != self.id);
+        }
+    }
+
+    /// Detach debugger and let vm run freely, see [SqDebugger::detach_hooks].
+    ///
+    /// Returned vm is closed on drop, forget it if vm is owned by someone else
+    pub fn detach(mut self) -> SafeVm {
+        self.detach_hooks();
+        self.vm.take().unwrap()
+    }
+}
+
+impl Drop for SqDebugger {
+    fn drop(&mut self) {
+        self.unregister();
+
+        // Vm is closed with debugger, so hooks and their references are released while it is alive
+        if let Some(vm) = &mut self.vm {
+            vm.remove_debug_hook();
+            vm.remove_error_handler();
+        }
     }
 }
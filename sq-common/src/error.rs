@@ -58,6 +58,11 @@ pub enum SqStackErrorReason {
     SqVmError(SqVmError),
     #[error("reached max expansion depth")]
     MaxDepthReached,
+    #[error("element {idx}: {reason}")]
+    ElementError {
+        idx: usize,
+        reason: Box<SqStackError>
+    },
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -69,6 +74,16 @@ pub struct SqStackError {
     msg: &'static str    
 }
 
+impl SqStackError {
+    /// Wrap error of container element, keeping its index
+    pub fn into_element_error(self, idx: usize, msg: &'static str) -> SqStackError {
+        SqStackError {
+            reason: SqStackErrorReason::ElementError { idx, reason: Box::new(self) },
+            msg
+        }
+    }
+}
+
 pub type SqDebugResult<T> = Result<T, SqDebugError>;
 
 #[derive(Debug, Error)]
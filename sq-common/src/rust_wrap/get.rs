@@ -3,10 +3,12 @@ use std::hash::Hash;
 
 
 use crate::sq_validate;
-use crate::{error::*, util::cstr_to_string};
+use crate::error::*;
 use super::types::*;
 use super::vm::{Vm, safety::VmDrop};
 use super::push::{SqPush, IntoPushResult};
+use super::api::VmRawApi;
+use super::layout;
 
 pub type SqGetResult<T> = Result<T, SqStackError>;
 
@@ -73,6 +75,11 @@ impl<S> SqGet<SqFloat> for Vm<S> where S: VmDrop {
     }
 }
 
+/// Opaque handle passed through vm, e.g. by native bindings of host api.
+///
+/// Safety: vm stores only the address, pointee type is not checked and its lifetime
+/// is not tracked. Pointer may be dangling or point to other type than `T`, 
+/// so dereferencing it is up to caller and as unsafe as with any raw pointer
 impl<S, T> SqGet<SqUserPointer<T>> for Vm<S> where S: VmDrop {
     fn get_constrain(&self, idx: isize, _: Option<usize>) -> SqGetResult<SqUserPointer<T>> {
         sq_validate!(self.get_type(idx), SqType::UserPointer)
@@ -82,16 +89,20 @@ impl<S, T> SqGet<SqUserPointer<T>> for Vm<S> where S: VmDrop {
     }
 }
 
-// TODO: Check encoding
-// TODO: Use get_size() to make this more safe
+// Squirrel strings are byte strings with known length and may contain
+// zeroes or non-utf8 bytes, so they are copied by size and converted lossy
 impl<S> SqGet<String> for Vm<S> where S: VmDrop {
     fn get_constrain(&self, idx: isize, _: Option<usize>) -> SqGetResult<String> {
         sq_validate!(self.get_type(idx), SqType::String)
             .map_err(|e| e.into_stack_error("failed to get string"))?;
-        unsafe {
-            let ptr = self.get_string(idx).unwrap();  
-            Ok(cstr_to_string(ptr as _))
-        }
+        let size = self.get_size(idx)
+            .map_err(|e| e.into_stack_error("failed to get size of string"))?
+            as usize;
+        let bytes = unsafe {
+            let ptr = self.get_string(idx).unwrap();
+            std::slice::from_raw_parts(ptr, size)
+        };
+        Ok(String::from_utf8_lossy(bytes).into_owned())
     }
 }
 
@@ -143,8 +154,8 @@ where
 
         let mut out = vec![];
 
-        for elem in self.iter_array(idx, max_depth) {
-            out.push(elem?);
+        for (i, elem) in self.iter_array(idx, max_depth).enumerate() {
+            out.push(elem.map_err(|e| e.into_element_error(i, "failed to get array"))?);
         }
 
         Ok(out)
@@ -220,8 +231,15 @@ impl<S> SqGet<SqInstance> for Vm<S> where S: VmDrop {
 }
 
 impl<S> SqGet<SqClosureInfo> for Vm<S> where S: VmDrop {
-    fn get_constrain(&self, idx: isize, _: Option<usize>) -> SqGetResult<SqClosureInfo> {
-        let mut info = SqClosureInfo { name: None, args: vec![], src: None };
+    fn get_constrain(&self, idx: isize, max_depth: Option<usize>) -> SqGetResult<SqClosureInfo> {
+        let mut info = SqClosureInfo { 
+            name: None,
+            args: vec![],
+            src: None,
+            varargs: false,
+            defparams: vec![],
+            upvalues: IndexMap::new(),
+        };
 
         self.get_closure_info(idx)
             .map_err(|e| e.into_stack_error("failed to get closure info"))?;
@@ -245,6 +263,15 @@ impl<S> SqGet<SqClosureInfo> for Vm<S> where S: VmDrop {
                 "src" => if let DynSqVar::String(n_src) = val {
                     info.src = Some(n_src)
                 }
+                // May be stored either as bool or as SQBool integer
+                "varargs" => match val {
+                    DynSqVar::Bool(b) => info.varargs = b,
+                    DynSqVar::Integer(i) => info.varargs = i != 0,
+                    _ => ()
+                }
+                "defparams" => if let DynSqVar::Array(v) = val {
+                    info.defparams = v
+                }
                 _ => ()
             }
         }
@@ -252,6 +279,18 @@ impl<S> SqGet<SqClosureInfo> for Vm<S> where S: VmDrop {
         // Pop closure info
         self.pop(1);
 
+        // Free variables are expanded like container children, only if requested
+        if self.upvalues.get() && max_depth != Some(0) {
+            let depth = max_depth.map(|d| d - 1);
+            let mut nval = 0;
+            while let Some(name) = self.get_free_variable(idx, nval) {
+                let val = self.get_constrain(-1, depth);
+                self.pop(1);
+                info.upvalues.insert(name, val?);
+                nval += 1;
+            }
+        }
+
         Ok(info)
     }
 }
@@ -301,34 +340,115 @@ impl<S> SqGet<SqNativeClosureInfo> for Vm<S> where S: VmDrop {
     }
 }
 
+impl<S> SqGet<SqGeneratorState> for Vm<S> where S: VmDrop {
+    fn get_constrain(&self, idx: isize, _: Option<usize>) -> SqGetResult<SqGeneratorState> {
+        sq_validate!(self.get_type(idx), SqType::Generator)
+            .map_err(|e| e.into_stack_error("failed to get generator"))?;
+
+        // State is reported by generator's default delegate
+        let base = self.api().stack_top();
+        self.push("getstatus");
+        let status = self.slot_get(idx - idx.is_negative() as isize)
+            .map_err(|e| e.into_stack_error("failed to get generator status method"))
+            .and_then(|_| {
+                // Generator itself is `this`
+                self.api().clone_idx(idx - idx.is_negative() as isize);
+                self.closure_call(1, Some(0))
+            });
+
+        // Pop getstatus closure
+        self.api().set_stack_top(base);
+
+        let status = status?;
+        match status {
+            DynSqVar::String(s) if s == "running" => Ok(SqGeneratorState::Running),
+            DynSqVar::String(s) if s == "suspended" => Ok(SqGeneratorState::Suspended),
+            DynSqVar::String(s) if s == "dead" => Ok(SqGeneratorState::Dead),
+            _ => Err(SqVmError::other(format!("unknown generator status: {status}"))
+                .into_stack_error("failed to get generator status")),
+        }
+    }
+}
+
+impl<S> SqGet<SqGeneratorInfo> for Vm<S> where S: VmDrop {
+    fn get_constrain(&self, idx: isize, max_depth: Option<usize>) -> SqGetResult<SqGeneratorInfo> {
+        let state = self.get_constrain(idx, None)?;
+        if state != SqGeneratorState::Suspended || max_depth == Some(0) {
+            return Ok(SqGeneratorInfo { state, frame: None })
+        }
+
+        // Saved frame is not reachable through the api, read generator internals
+        let ptr = self.get_object_ptr(idx)
+            .map_err(|e| e.into_stack_error("failed to get generator"))?;
+        // SAFETY: generator is kept alive by the stack and checked to be suspended
+        let raw = unsafe { layout::suspended_frame(ptr) }
+            .map_err(|e| SqVmError::other(e).into_stack_error("failed to read suspended generator frame"))?;
+
+        let mut locals = IndexMap::with_capacity(raw.locals.len());
+        for (name, val) in &raw.locals {
+            self.push_stack_obj(name);
+            let name = self.get_constrain(-1, None);
+            self.pop(1);
+
+            self.push_stack_obj(val);
+            let val = self.get_constrain(-1, max_depth.map(|d| d - 1));
+            self.pop(1);
+
+            locals.insert(name?, val?);
+        }
+
+        Ok(SqGeneratorInfo { state, frame: Some(SqSuspendedFrame { line: raw.line, locals }) })
+    }
+}
+
 impl<S> SqGet<DynSqVar> for Vm<S> where S: VmDrop {
     fn get_constrain(&self, idx: isize, max_depth: Option<usize>) -> SqGetResult<DynSqVar> {
         let sq_type = self.get_type(idx);
 
         // If container, do not expand
         if matches!(max_depth, Some(depth) 
-            if depth == 0 && sq_type.is_complex() && !sq_type.is_closure()
+            if depth == 0 && sq_type.is_complex() && !sq_type.is_closure() 
+                && sq_type != SqType::Generator
         ) {
             return Ok(DynSqVar::NotExpanded(sq_type))
         }
 
+        // Containers may reference themselves, do not expand ones that are already being expanded
+        let ptr = match sq_type {
+            SqType::Table | SqType::Class | SqType::Instance | SqType::Array | SqType::Closure
+                => self.get_object_ptr(idx).ok(),
+            _ => None,
+        };
+        if let Some(ptr) = ptr {
+            if self.expanding.borrow().contains(&ptr) {
+                return Ok(DynSqVar::Cycle(sq_type))
+            }
+            self.expanding.borrow_mut().push(ptr);
+        }
+
         // Otherwise just pass max_depth without changes
-        match self.get_type(idx) {
+        let res = match sq_type {
             SqType::Null => Ok(DynSqVar::Null),
-            SqType::Integer => Ok(DynSqVar::Integer(self.get_constrain(idx, max_depth)?)),
-            SqType::String => Ok(DynSqVar::String(self.get_constrain(idx, max_depth)?)),
-            SqType::Table => Ok(DynSqVar::Table(self.get_constrain(idx, max_depth)?)),
-            SqType::Class => Ok(DynSqVar::Class(self.get_constrain(idx, max_depth)?)),
-            SqType::Instance => Ok(DynSqVar::Instance(self.get_constrain(idx, max_depth)?)),
-            SqType::Array => Ok(DynSqVar::Array(self.get_constrain(idx, max_depth)?)),
-            SqType::Float => Ok(DynSqVar::Float(self.get_constrain(idx, max_depth)?)),
-            SqType::Bool => Ok(DynSqVar::Bool(self.get_constrain(idx, max_depth)?)),
-            SqType::UserData => Ok(DynSqVar::UserData(self.get_constrain(idx, max_depth)?)),
-            SqType::UserPointer => Ok(DynSqVar::UserPointer(self.get_constrain(idx, max_depth)?)),
-            SqType::Closure => Ok(DynSqVar::Closure(self.get_constrain(idx, max_depth)?)),
-            SqType::NativeClosure => Ok(DynSqVar::NativeClosure(self.get_constrain(idx, max_depth)?)),
+            SqType::Integer => self.get_constrain(idx, max_depth).map(DynSqVar::Integer),
+            SqType::String => self.get_constrain(idx, max_depth).map(DynSqVar::String),
+            SqType::Table => self.get_constrain(idx, max_depth).map(DynSqVar::Table),
+            SqType::Class => self.get_constrain(idx, max_depth).map(DynSqVar::Class),
+            SqType::Instance => self.get_constrain(idx, max_depth).map(DynSqVar::Instance),
+            SqType::Array => self.get_constrain(idx, max_depth).map(DynSqVar::Array),
+            SqType::Float => self.get_constrain(idx, max_depth).map(DynSqVar::Float),
+            SqType::Bool => self.get_constrain(idx, max_depth).map(DynSqVar::Bool),
+            SqType::UserData => self.get_constrain(idx, max_depth).map(DynSqVar::UserData),
+            SqType::UserPointer => self.get_constrain(idx, max_depth).map(DynSqVar::UserPointer),
+            SqType::Closure => self.get_constrain(idx, max_depth).map(DynSqVar::Closure),
+            SqType::NativeClosure => self.get_constrain(idx, max_depth).map(DynSqVar::NativeClosure),
+            SqType::Generator => self.get_constrain(idx, max_depth).map(DynSqVar::Generator),
             other => Ok(DynSqVar::NotExpanded(other)),
+        };
+
+        if ptr.is_some() {
+            self.expanding.borrow_mut().pop();
         }
+        res
     }
 }
 
@@ -0,0 +1,315 @@
+//! Layouts of squirrel internals, that are not reachable through the api.
+//!
+//! Mirror Squirrel 2.2 headers (`sqobject.h`, `sqclosure.h`, `sqfuncproto.h`, `sqvm.h`)
+//! built with garbage collector. Only leading fields, that are actually read, are declared.
+//! Type tags of read objects are validated, so mismatched layout is reported, not followed
+
+use libc::c_void;
+
+use super::api::{
+    SQObject, tagSQObjectType_OT_CLOSURE, tagSQObjectType_OT_FUNCPROTO, tagSQObjectType_OT_STRING,
+    tagSQObjectType_OT_TABLE, tagSQObjectType_OT_NULL, tagSQObjectType_OT_NATIVECLOSURE,
+};
+
+/// `_OP_TAILCALL` of `sqopcodes.h`, closure is in register `arg1`
+const OP_TAILCALL: u8 = 0x05;
+/// `_OP_CALL`, closure is in register `arg1`
+const OP_CALL: u8 = 0x06;
+/// `_OP_PREPCALL`, closure is got by key from register and stored in register `arg0`
+const OP_PREPCALL: u8 = 0x07;
+/// `_OP_PREPCALLK`, closure is got by key from literal `arg1` and stored in register `arg0`
+const OP_PREPCALLK: u8 = 0x08;
+
+/// `SQRefCounted`
+#[repr(C)]
+struct RefCounted {
+    vtable: *const c_void,
+    ui_ref: usize,
+    weakref: *mut c_void,
+}
+
+/// `SQCollectable`, base of objects, that may be part of reference cycles
+#[repr(C)]
+struct Collectable {
+    base: RefCounted,
+    next: *mut c_void,
+    prev: *mut c_void,
+    sharedstate: *mut c_void,
+}
+
+/// `sqvector<SQObjectPtr>`
+#[repr(C)]
+struct ObjectVec {
+    vals: *const SQObject,
+    size: usize,
+    allocated: usize,
+}
+
+/// `SQVM`, leading fields
+#[repr(C)]
+struct VmState {
+    base: Collectable,
+    stack: ObjectVec,
+    vargsstack: ObjectVec,
+    top: isize,
+    stackbase: isize,
+    roottable: SQObject,
+    lasterror: SQObject,
+    errorhandler: SQObject,
+    debughook: SQObject,
+}
+
+/// `SQInstruction`
+#[repr(C)]
+struct Instruction {
+    arg1: i32,
+    op: u8,
+    arg0: u8,
+    arg2: u8,
+    arg3: u8,
+}
+
+/// `SQVM::CallInfo`, leading fields
+#[repr(C)]
+struct CallInfo {
+    /// Next instruction to execute
+    ip: *const Instruction,
+    literals: *const SQObject,
+    closure: SQObject,
+}
+
+/// `SQGenerator`, leading fields
+#[repr(C)]
+struct Generator {
+    base: Collectable,
+    closure: SQObject,
+    /// Frame stack saved on yield, indexed by local positions
+    stack: ObjectVec,
+    vargsstack: ObjectVec,
+    ci: CallInfo,
+}
+
+/// `SQClosure`, leading fields
+#[repr(C)]
+struct Closure {
+    base: Collectable,
+    env: SQObject,
+    function: SQObject,
+}
+
+/// `SQLocalVarInfo`
+#[repr(C)]
+struct LocalVarInfo {
+    name: SQObject,
+    start_op: usize,
+    end_op: usize,
+    pos: usize,
+}
+
+/// `SQLineInfo`
+#[repr(C)]
+struct LineInfo {
+    line: isize,
+    op: isize,
+}
+
+/// `SQFunctionProto`
+#[repr(C)]
+struct FunctionProto {
+    base: RefCounted,
+    sourcename: SQObject,
+    name: SQObject,
+    stacksize: isize,
+    bgenerator: bool,
+    varparams: bool,
+    nlocalvarinfos: isize,
+    localvarinfos: *const LocalVarInfo,
+    nlineinfos: isize,
+    lineinfos: *const LineInfo,
+    nliterals: isize,
+    literals: *const SQObject,
+    nparameters: isize,
+    parameters: *const SQObject,
+    nfunctions: isize,
+    functions: *const SQObject,
+    noutervalues: isize,
+    outervalues: *const c_void,
+    ndefaultparams: isize,
+    defaultparams: *const isize,
+    ninstructions: isize,
+    instructions: [Instruction; 1],
+}
+
+/// Compiled function, read from `SQFunctionProto`
+pub(crate) struct RawFunction {
+    pub name: SQObject,
+    pub sourcename: SQObject,
+    /// Line info table: `(instruction offset, line)`, as stored by compiler
+    pub lines: Vec<(isize, isize)>,
+    /// Calls of closures got by name from slot: `(name literal, line of call)`
+    pub calls: Vec<(SQObject, Option<isize>)>,
+    /// Count of calls of closures from locals or expressions, which can't be resolved statically
+    pub indirect_calls: usize,
+    /// Protos of functions declared inside this one
+    pub functions: Vec<usize>,
+}
+
+/// Same lookup as `SQFunctionProto::GetLine`, `ip` is offset of instruction after current
+fn line_at(lineinfos: &[LineInfo], ip: isize) -> Option<isize> {
+    lineinfos.first().map(|first| {
+        let mut line = first.line;
+        for info in &lineinfos[1..] {
+            if info.op >= ip {
+                break;
+            }
+            line = info.line;
+        }
+        line
+    })
+}
+
+/// Get pointer to function proto of script closure.
+///
+/// # Safety
+/// `closure` must be pointer from handler of alive script closure
+pub(crate) unsafe fn closure_function(closure: usize) -> Result<usize, &'static str> {
+    let closure = &*(closure as *const Closure);
+    if closure.function._type != tagSQObjectType_OT_FUNCPROTO {
+        return Err("unsupported closure layout");
+    }
+    Ok(closure.function._unVal.pUserPointer as usize)
+}
+
+/// Slice of `len` elements, which is empty for null `ptr`
+unsafe fn slice<'a, T>(ptr: *const T, len: isize) -> &'a [T] {
+    if ptr.is_null() || len <= 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, len as usize)
+    }
+}
+
+/// Check that proto layout matches and get it
+unsafe fn proto<'a>(proto: usize) -> Result<&'a FunctionProto, &'static str> {
+    let proto = &*(proto as *const FunctionProto);
+    if proto.sourcename._type != tagSQObjectType_OT_STRING 
+        || proto.nlineinfos < 0 
+        || proto.ninstructions < 0 
+        || proto.nliterals < 0 
+        || proto.nfunctions < 0 
+    {
+        return Err("unsupported function layout");
+    }
+    Ok(proto)
+}
+
+/// Read line info, call sites and nested functions of function proto.
+///
+/// Calls are resolved by pairing `_OP_PREPCALLK` with call instruction, that uses its register.
+///
+/// # Safety
+/// `proto` must be pointer to alive function proto, e.g. from [closure_function]
+pub(crate) unsafe fn function(proto: usize) -> Result<RawFunction, &'static str> {
+    let proto = self::proto(proto)?;
+
+    let lineinfos = slice(proto.lineinfos, proto.nlineinfos);
+    let instructions = slice(proto.instructions.as_ptr(), proto.ninstructions);
+    let literals = slice(proto.literals, proto.nliterals);
+    let functions = slice(proto.functions, proto.nfunctions);
+
+    // Register of prepared closure -> name literal, if it was got by constant key
+    let mut prepared: Vec<(u8, Option<SQObject>)> = vec![];
+    let mut calls = vec![];
+    let mut indirect_calls = 0;
+    for (op, inst) in instructions.iter().enumerate() {
+        match inst.op {
+            OP_PREPCALLK | OP_PREPCALL => {
+                let name = literals.get(inst.arg1 as usize)
+                    .filter(|lit| inst.op == OP_PREPCALLK && lit._type == tagSQObjectType_OT_STRING)
+                    .copied();
+                prepared.retain(|(reg, _)| *reg != inst.arg0);
+                prepared.push((inst.arg0, name));
+            }
+            OP_CALL | OP_TAILCALL => {
+                let reg = u8::try_from(inst.arg1).ok();
+                let name = prepared.iter()
+                    .position(|(r, _)| Some(*r) == reg)
+                    .and_then(|idx| prepared.remove(idx).1);
+                match name {
+                    Some(name) => calls.push((name, line_at(lineinfos, op as isize + 1))),
+                    None => indirect_calls += 1,
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(RawFunction {
+        name: proto.name,
+        sourcename: proto.sourcename,
+        lines: lineinfos.iter().map(|info| (info.op, info.line)).collect(),
+        calls,
+        indirect_calls,
+        functions: functions.iter()
+            .filter(|f| f._type == tagSQObjectType_OT_FUNCPROTO)
+            .map(|f| f._unVal.pUserPointer as usize)
+            .collect(),
+    })
+}
+
+/// Frame of suspended generator: yield line and `(name, value)` handlers of alive locals
+pub(crate) struct RawSuspendedFrame {
+    pub line: Option<isize>,
+    pub locals: Vec<(SQObject, SQObject)>,
+}
+
+/// Read saved frame of suspended generator.
+///
+/// # Safety
+/// `generator` must be pointer from handler of alive generator, that is suspended
+pub(crate) unsafe fn suspended_frame(generator: usize) -> Result<RawSuspendedFrame, &'static str> {
+    let generator = &*(generator as *const Generator);
+    if generator.closure._type != tagSQObjectType_OT_CLOSURE {
+        return Err("unsupported generator layout");
+    }
+
+    let proto = proto(closure_function(generator.closure._unVal.pUserPointer as usize)?)?;
+    if !proto.bgenerator {
+        return Err("unsupported function layout");
+    }
+
+    let instructions = proto.instructions.as_ptr();
+    let op = generator.ci.ip.offset_from(instructions);
+    if !(0..=proto.ninstructions).contains(&op) {
+        return Err("yield point is outside of function");
+    }
+
+    let line = line_at(slice(proto.lineinfos, proto.nlineinfos), op);
+
+    // Same visibility as `SQFunctionProto::GetLocal`, `ip` points past yield
+    let nop = (op - 1).max(0) as usize;
+    let stack = std::slice::from_raw_parts(generator.stack.vals, generator.stack.size);
+    let localvarinfos = slice(proto.localvarinfos, proto.nlocalvarinfos);
+    let locals = localvarinfos.iter()
+        .filter(|info| info.start_op <= nop && info.end_op >= nop)
+        .filter_map(|info| Some((info.name, *stack.get(info.pos)?)))
+        .collect();
+
+    Ok(RawSuspendedFrame { line, locals })
+}
+
+/// Read handler of debug hook closure of vm, which is null if hook is not set.
+///
+/// # Safety
+/// `vm` must be handle of alive vm and `root_table` pointer of its root table
+pub(crate) unsafe fn debug_hook(vm: usize, root_table: usize) -> Result<SQObject, &'static str> {
+    let vm = &*(vm as *const VmState);
+    if vm.roottable._type != tagSQObjectType_OT_TABLE || vm.roottable._unVal.pUserPointer as usize != root_table {
+        return Err("unsupported vm layout");
+    }
+
+    match vm.debughook._type {
+        tagSQObjectType_OT_NULL | tagSQObjectType_OT_CLOSURE | tagSQObjectType_OT_NATIVECLOSURE => Ok(vm.debughook),
+        _ => Err("unsupported vm layout"),
+    }
+}
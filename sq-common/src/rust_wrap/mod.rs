@@ -8,4 +8,5 @@ pub mod push;
 pub mod obj;
 pub mod iter;
 pub mod throw;
+mod layout;
 
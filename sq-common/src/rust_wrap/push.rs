@@ -79,6 +79,11 @@ impl<S> SqPush<isize> for Vm<S> where S: VmDrop {
     }
 }
 
+/// Pointer is pushed as userpointer, pointee is neither copied nor owned by vm.
+///
+/// Safety: pushing is safe itself, but pointee must outlive every script
+/// that may pass the pointer back to native code, see [SqGet] of [SqUserPointer].
+/// Use [SqUserData] when vm should own the data
 impl<S, T> SqPush<SqUserPointer<T>> for Vm<S> where S: VmDrop {
     type Output = ();
     
@@ -208,7 +213,8 @@ where
         self.new_array(val.len());
         for (index, elem) in val.into_iter().enumerate() {
             self.push(index as isize);
-            self.push(elem).into_result()?;
+            self.push(elem).into_result()
+                .map_err(|e| e.into_element_error(index, "failed to push array"))?;
             self.slot_set(-3)
                 .map_err(|e| e.into_stack_error("failed to set array slot"))?;
         }
@@ -5,6 +5,7 @@ use std::fmt::Write;
 use indexmap::IndexMap;
 
 use bitflags::bitflags;
+use serde::{Serialize, Serializer, ser::SerializeMap};
 
 use super::api::*;
 use super::vm;
@@ -148,6 +149,35 @@ pub struct SqClosureInfo {
     pub name: Option<String>,
     pub args: Vec<String>,
     pub src: Option<String>,
+    /// Closure accepts variable arguments count
+    pub varargs: bool,
+    /// Default values of the last arguments
+    pub defparams: Vec<DynSqVar>,
+    /// Captured free variables. Fetched as container children inside of
+    /// [Vm::with_upvalues](super::vm::Vm::with_upvalues) only, not displayed unless requested
+    pub upvalues: IndexMap<String, DynSqVar>,
+}
+
+/// Compiled script function, read from its proto
+#[derive(Clone, Debug)]
+pub struct SqFunctionProto {
+    pub name: Option<String>,
+    pub src: Option<String>,
+    /// Line info table: `(instruction offset, source line)`, sorted by offset
+    pub lines: Vec<(isize, isize)>,
+    /// Functions called by slot name, e.g. `foo()` or `obj.foo()`: `(callee name, line of call)`
+    pub calls: Vec<(String, Option<isize>)>,
+    /// Count of calls through locals or expressions, which targets can't be found statically
+    pub indirect_calls: usize,
+}
+
+impl SqFunctionProto {
+    /// First and last lines of function, that have instructions
+    pub fn line_range(&self) -> Option<(isize, isize)> {
+        let first = self.lines.iter().map(|(_, line)| *line).min()?;
+        let last = self.lines.iter().map(|(_, line)| *line).max()?;
+        Some((first, last))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -156,9 +186,57 @@ pub struct SqNativeClosureInfo {
     pub arg_types: Vec<SqTypedArgMask>,
 }
 
+/// Generator status as reported by its `getstatus()` method
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SqGeneratorState {
+    Running,
+    Suspended,
+    Dead,
+}
+
+impl std::fmt::Display for SqGeneratorState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Running => "running",
+            Self::Suspended => "suspended",
+            Self::Dead => "dead",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Generator and, if it is suspended, its saved frame
+#[derive(Clone, Debug)]
+pub struct SqGeneratorInfo {
+    pub state: SqGeneratorState,
+    /// Frame saved at `yield`. Fetched as container children
+    pub frame: Option<SqSuspendedFrame>,
+}
+
+/// Frame of generator suspended at `yield`
+#[derive(Clone, Debug)]
+pub struct SqSuspendedFrame {
+    /// Line of the `yield` generator will resume from
+    pub line: Option<isize>,
+    /// Locals alive at the `yield`
+    pub locals: IndexMap<String, DynSqVar>,
+}
+
 #[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
 pub struct SqNull;
 
+/// Rendering of integers in pretty-print
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum SqIntFormat {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+    Oct,
+    /// Decimal followed by character with this code, if any
+    Char,
+}
+
 /// Rust representation of dynamically-typed squirrel variable
 #[derive(Clone, Debug)]
 pub enum DynSqVar {
@@ -175,7 +253,10 @@ pub enum DynSqVar {
     UserPointer(SqUserPointer<u8>),
     Closure(SqClosureInfo),
     NativeClosure(SqNativeClosureInfo),
-    NotExpanded(SqType)
+    Generator(SqGeneratorInfo),
+    NotExpanded(SqType),
+    /// Container that references itself: it was already being expanded higher in the same value
+    Cycle(SqType),
 } 
 
 // Due to userpointer
@@ -198,7 +279,36 @@ impl DynSqVar {
             Self::UserPointer(_) => SqType::UserPointer,
             Self::Closure(_) => SqType::Closure,
             Self::NativeClosure(_) => SqType::NativeClosure,
-            Self::NotExpanded(t) => *t,
+            Self::Generator(_) => SqType::Generator,
+            Self::NotExpanded(t) | Self::Cycle(t) => *t,
+        }
+    }
+
+    /// Compare values by content, including floats and containers.
+    ///
+    /// Unlike [PartialEq], which is used for table keys, 
+    /// recursively compares tables, classes and instances
+    pub fn deep_eq(&self, other: &Self) -> bool {
+        let maps_eq = |l: &SqTable, r: &SqTable| l.len() == r.len() 
+            && l.iter().all(|(k, v)| matches!(r.get(k), Some(rv) if v.deep_eq(rv)));
+
+        match (self, other) {
+            (Self::Float(l0), Self::Float(r0)) => l0 == r0,
+            (Self::Array(l0), Self::Array(r0)) => l0.len() == r0.len()
+                && l0.iter().zip(r0).all(|(l, r)| l.deep_eq(r)),
+            (Self::Table(l0), Self::Table(r0))
+            | (Self::Class(l0), Self::Class(r0))
+            | (Self::Instance(SqInstance { this: l0 }), Self::Instance(SqInstance { this: r0 }))
+                => maps_eq(l0, r0),
+            (Self::UserData(l0), Self::UserData(r0)) => l0.0 == r0.0,
+            (Self::UserPointer(l0), Self::UserPointer(r0)) => l0 == r0,
+            (Self::Generator(l0), Self::Generator(r0)) => l0.state == r0.state
+                && match (&l0.frame, &r0.frame) {
+                    (Some(l), Some(r)) => l.line == r.line && l.locals.len() == r.locals.len()
+                        && l.locals.iter().all(|(k, v)| matches!(r.locals.get(k), Some(rv) if v.deep_eq(rv))),
+                    (l, r) => l.is_none() && r.is_none(),
+                },
+            _ => self == other,
         }
     }
 
@@ -209,13 +319,123 @@ impl DynSqVar {
         Ok(())
     }
 
+    /// Get displayable wrapper, that renders value as squirrel source literal
+    pub fn as_source(&self) -> SqSource<'_> {
+        SqSource(self)
+    }
+
+    /// Indented squirrel literal print helper
+    fn fmt_source(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+        const INDENT_INC: usize = 4;
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Integer(i) => write!(f, "{i}"),
+            // Keep float literal a float
+            Self::Float(flt) if flt.fract() == 0.0 && flt.is_finite() => write!(f, "{flt:.1}"),
+            Self::Float(flt) => write!(f, "{flt}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\0' => write!(f, "\\0")?,
+                        c if c.is_control() => write!(f, "\\x{:04x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+
+            Self::Table(map) => {
+                if map.is_empty() {
+                    return write!(f, "{{}}")
+                }
+
+                writeln!(f, "{{")?;
+                for (key, val) in map {
+                    Self::write_spaces(f, indent + INDENT_INC)?;
+                    match key {
+                        Self::String(s) if Self::is_identifier(s) => write!(f, "{s} = ")?,
+                        key => {
+                            write!(f, "[")?;
+                            key.fmt_source(f, indent + INDENT_INC)?;
+                            write!(f, "] = ")?;
+                        }
+                    }
+                    val.fmt_source(f, indent + INDENT_INC)?;
+                    writeln!(f, ",")?;
+                }
+                Self::write_spaces(f, indent)?;
+                write!(f, "}}")
+            }
+
+            Self::Array(v) => {
+                if v.is_empty() {
+                    return write!(f, "[]")
+                }
+
+                writeln!(f, "[")?;
+                for var in v {
+                    Self::write_spaces(f, indent + INDENT_INC)?;
+                    var.fmt_source(f, indent + INDENT_INC)?;
+                    writeln!(f, ",")?;
+                }
+                Self::write_spaces(f, indent)?;
+                write!(f, "]")
+            }
+
+            // Can not be literalized
+            Self::NotExpanded(t) => write!(f, "null /* {t:?}, not expanded */"),
+            Self::Cycle(t) => write!(f, "null /* {t:?}, cycle */"),
+            other => write!(f, "null /* {:?} */", other.get_type()),
+        }
+    }
+
+    /// Check if string can be used as table key without brackets
+    pub fn is_identifier(s: &str) -> bool {
+        let mut chars = s.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Get displayable wrapper, that also shows captured variables of closures
+    pub fn with_upvalues(&self) -> SqFormatted<'_> {
+        self.formatted(true, SqIntFormat::Dec)
+    }
+
+    /// Get displayable wrapper with optional captured variables of closures 
+    /// and integers rendered in specified format, including nested ones
+    pub fn formatted(&self, upvalues: bool, ints: SqIntFormat) -> SqFormatted<'_> {
+        SqFormatted { var: self, upvalues, ints }
+    }
+
     /// Indented pretty-print helper
-    fn fmt_indent(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+    fn fmt_indent(
+        &self, 
+        f: &mut std::fmt::Formatter<'_>, 
+        indent: usize, 
+        upvalues: bool, 
+        ints: SqIntFormat,
+    ) -> std::fmt::Result {
         const INDENT_INC: usize = 4;
         const HEXDUMP_W: usize = 16;
         match self {
             Self::Null => write!(f, "null"),
-            Self::Integer(i) => write!(f, "{i}"),
+            Self::Integer(i) => match ints {
+                SqIntFormat::Dec => write!(f, "{i}"),
+                SqIntFormat::Hex => write!(f, "{i:#x}"),
+                SqIntFormat::Bin => write!(f, "{i:#b}"),
+                SqIntFormat::Oct => write!(f, "{i:#o}"),
+                SqIntFormat::Char => match u32::try_from(*i).ok().and_then(char::from_u32) {
+                    Some(c) => write!(f, "{i} {c:?}"),
+                    None => write!(f, "{i}"),
+                },
+            },
             Self::Float(flt) => write!(f, "{flt}"),
             Self::Bool(b) => write!(f, "{b}"),
             Self::String(s) => write!(f, "\"{s}\""),
@@ -238,7 +458,7 @@ impl DynSqVar {
                 for (key, val) in map {
                     Self::write_spaces(f, indent + INDENT_INC)?;
                     write!(f, "{key} <- ")?;
-                    val.fmt_indent(f, indent + INDENT_INC)?;
+                    val.fmt_indent(f, indent + INDENT_INC, upvalues, ints)?;
                     writeln!(f, ",")?;
                 }
                 Self::write_spaces(f, indent)?;
@@ -255,7 +475,7 @@ impl DynSqVar {
                 writeln!(f, "[")?;
                 for var in v {
                     Self::write_spaces(f, indent + INDENT_INC)?;
-                    var.fmt_indent(f, indent + INDENT_INC)?;
+                    var.fmt_indent(f, indent + INDENT_INC, upvalues, ints)?;
                     writeln!(f, ",")?;
                 }
                 Self::write_spaces(f, indent)?;
@@ -295,18 +515,46 @@ impl DynSqVar {
 
             Self::UserPointer(p) => write!(f, "ptr {p:p}"),
 
-            Self::Closure(SqClosureInfo { name, args, .. }) => {
+            Self::Closure(SqClosureInfo { name, args, varargs, defparams, upvalues: outer, .. }) => {
                 let name = name.as_deref().unwrap_or("function");
                 write!(f, "closure {name}(")?;
 
+                // Default values are bound to the last arguments
+                let first_def = args.len().saturating_sub(defparams.len());
                 for (idx, arg) in args.iter().enumerate() {
                     if idx > 0 {
                         write!(f, ", ")?;
                     }
                     write!(f, "{arg}")?;
+
+                    if idx >= first_def {
+                        write!(f, " = {}", defparams[idx - first_def])?;
+                    }
                 }
 
-                write!(f, ")")
+                if *varargs {
+                    if !args.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "...")?;
+                }
+
+                write!(f, ")")?;
+
+                if !upvalues || outer.is_empty() {
+                    return Ok(())
+                }
+
+                // Captured variables as pseudo-container
+                writeln!(f, " outer {{")?;
+                for (name, val) in outer {
+                    Self::write_spaces(f, indent + INDENT_INC)?;
+                    write!(f, "{name} = ")?;
+                    val.fmt_indent(f, indent + INDENT_INC, upvalues, ints)?;
+                    writeln!(f, ",")?;
+                }
+                Self::write_spaces(f, indent)?;
+                write!(f, "}}")
             }
 
             Self::NativeClosure(SqNativeClosureInfo { name, arg_types }) => {
@@ -323,14 +571,91 @@ impl DynSqVar {
                 write!(f, ")")
             }
 
+            Self::Generator(SqGeneratorInfo { state, frame }) => {
+                write!(f, "generator ({state})")?;
+
+                let Some(SqSuspendedFrame { line, locals }) = frame else {
+                    return Ok(())
+                };
+
+                if let Some(line) = line {
+                    write!(f, " at line {line}")?;
+                }
+
+                // Saved locals as pseudo-container
+                writeln!(f, " locals {{")?;
+                for (name, val) in locals {
+                    Self::write_spaces(f, indent + INDENT_INC)?;
+                    write!(f, "{name} = ")?;
+                    val.fmt_indent(f, indent + INDENT_INC, upvalues, ints)?;
+                    writeln!(f, ",")?;
+                }
+                Self::write_spaces(f, indent)?;
+                write!(f, "}}")
+            }
+
             Self::NotExpanded(t) => write!(f, "{t:?}"),
+
+            Self::Cycle(_) => write!(f, "<cycle>"),
         }
     }
 }
 
+/// [DynSqVar] displayed as squirrel source literal
+pub struct SqSource<'a>(&'a DynSqVar);
+
+impl std::fmt::Display for SqSource<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_source(f, 0)
+    }
+}
+
+/// [DynSqVar] displayed with formatting options
+pub struct SqFormatted<'a> {
+    var: &'a DynSqVar,
+    /// Show captured variables of closures
+    upvalues: bool,
+    ints: SqIntFormat,
+}
+
+impl std::fmt::Display for SqFormatted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.var.fmt_indent(f, 0, self.upvalues, self.ints)
+    }
+}
+
 impl std::fmt::Display for DynSqVar {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.fmt_indent(f, 0)
+        self.fmt_indent(f, 0, false, SqIntFormat::Dec)
+    }
+}
+
+/// Values that can't be represented in data formats are serialized as their display strings
+impl Serialize for DynSqVar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => serializer.serialize_unit(),
+            Self::Integer(i) => serializer.serialize_i64(*i as i64),
+            Self::Float(f) => serializer.serialize_f64(*f as f64),
+            Self::Bool(b) => serializer.serialize_bool(*b),
+            Self::String(s) => serializer.serialize_str(s),
+            Self::Table(map)
+            | Self::Class(map)
+            | Self::Instance(SqInstance { this: map }) => {
+                let mut ser = serializer.serialize_map(Some(map.len()))?;
+                for (key, val) in map {
+                    // Most formats support only string keys
+                    match key {
+                        Self::String(s) => ser.serialize_entry(s, val)?,
+                        other => ser.serialize_entry(&other.to_string(), val)?,
+                    }
+                }
+                ser.end()
+            }
+            Self::Array(v) => serializer.collect_seq(v),
+            Self::UserData(u) => serializer.serialize_bytes(&u.0),
+            other => serializer.collect_str(other),
+        }
     }
 }
 
@@ -1,7 +1,11 @@
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::marker::PhantomData;
 use std::ptr::{addr_of_mut, addr_of};
+use std::sync::Arc;
+use log::warn;
 use delegate::delegate;
+use serde::Serialize;
 use sq_macro::sq_closure;
 
 use crate::{error::*, sq_validate};
@@ -9,13 +13,14 @@ use crate::util::cstr_to_string;
 
 use super::api::{
     self, VmRawApi, SQ_ERROR, SqCompilerErrorHandler, 
-    SqFunction, SqReleaseHook, SQObject, sq_resetobject
+    SqFunction, SqReleaseHook, SQObject, SQObjectType, sq_resetobject
 };
 use super::get::{SqGet, SqGetResult};
 use super::push::SqPush;
 use super::iter::{SqArrayIter, SqTableIter};
 use super::obj::SqObjectRef;
 use super::types::*;
+use super::layout;
 
 
 /// Strongly-typed vm errors
@@ -108,7 +113,49 @@ pub struct SqFunctionInfo {
     pub src_file: Option<String>,
 }
 
-#[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+/// Segment of path to local variable or its child
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum SqPathKey {
+    /// Local name or string slot key
+    Name(String),
+    /// Integer slot key, negative indices of arrays count from the end
+    Index(isize),
+    /// Array slice `start:end`, where bounds may be negative or omitted.
+    /// Only valid as the last key
+    Slice(String),
+}
+
+impl std::fmt::Display for SqPathKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) if DynSqVar::is_identifier(name) => write!(f, "{name}"),
+            Self::Name(name) => write!(f, "[\"{}\"]", name.replace('\\', "\\\\").replace('"', "\\\"")),
+            Self::Index(idx) => write!(f, "{idx}"),
+            Self::Slice(slice) => write!(f, "{slice}"),
+        }
+    }
+}
+
+/// Debug hook closure, that was set on vm not by this crate.
+///
+/// Holds a strong reference, so hook is alive until dropped
+pub struct SqForeignHook {
+    obj: SQObject,
+    vm: api::HSQUIRRELVM,
+}
+
+// Hook is called and released only by its vm
+unsafe impl Send for SqForeignHook {}
+unsafe impl Sync for SqForeignHook {}
+
+impl Drop for SqForeignHook {
+    fn drop(&mut self) {
+        let vm = unsafe { Vm::from_handle(self.vm).into_friend() };
+        vm.dec_ref(&mut self.obj);
+    }
+}
+
+#[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Serialize)]
 pub struct SqStackInfo {
     pub funcname: Option<String>,
     pub src_file: Option<String>,
@@ -126,12 +173,23 @@ impl std::fmt::Display for SqStackInfo {
 }
 
 /// SQVM  local variable
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SqLocalVar {
     pub name: String,
     pub val: DynSqVar,
 }
 
+/// Internal representation of SQVM object, for marshaling diagnostics
+#[derive(Clone, Debug)]
+pub struct SqRawObject {
+    /// Type tag of object
+    pub tag: SQObjectType,
+    /// Bytes of whole object handler: type tag, value union and padding
+    pub bytes: Vec<u8>,
+    /// Value decoded as usual, containers are not expanded
+    pub val: DynSqVar,
+}
+
 /// SQVM local variable handle
 pub struct SqLocalVarHandle<'vm, S> where S: safety::VmDrop {
     pub name: String,
@@ -155,6 +213,10 @@ impl<S> Drop for Vm<S> where S: safety::VmDrop {
 
 pub struct Vm<S> where S: safety::VmDrop  {
     api: VmApi,
+    /// Identities of containers that are being expanded by [SqGet], used to detect cycles
+    pub(super) expanding: RefCell<Vec<usize>>,
+    /// Free variables of closures are fetched by [SqGet], see [Vm::with_upvalues]
+    pub(super) upvalues: Cell<bool>,
     _safety: S
 }
 
@@ -298,6 +360,17 @@ impl<S> Vm<S> where S: safety::VmDrop {
         Ok(())
     }
 
+    /// Pops a key and a value from the stack and performs a set operation
+    /// on the object at position `idx` in the stack,
+    /// without employing delegation or metamethods.
+    ///
+    /// It only works on tables, instances, arrays and classes.
+    #[inline]
+    pub fn slot_set_raw(&self, idx: isize) -> SqVmResult<()> {
+        sq_try!{ self, unsafe { self.api().rawset(idx) } }?;
+        Ok(())
+    }
+
     /// Pushes in the stack the next key and value of an array, table or class slot. 
     /// 
     /// To start the iteration this function expects a `null` value on top of the stack.
@@ -353,6 +426,107 @@ impl<S> Vm<S> where S: safety::VmDrop {
         Ok(())
     }
 
+    /// Push free variable `nval` of closure on stack index `idx`.
+    ///
+    /// Returns name of the variable or `None` if it doesn't exist
+    pub fn get_free_variable(&self, idx: isize, nval: usize) -> Option<String> {
+        let ptr = unsafe { self.api().getfreevariable(idx, nval) };
+        if ptr != 0 as _ {
+            Some(unsafe { cstr_to_string(ptr) })
+        } else {
+            None
+        }
+    }
+
+    /// Run `f` with free variables of closures fetched as their children.
+    ///
+    /// By default closures are fetched without captured variables
+    pub fn with_upvalues<T>(&self, f: impl FnOnce() -> T) -> T {
+        let prev = self.upvalues.replace(true);
+        let res = f();
+        self.upvalues.set(prev);
+        res
+    }
+
+    /// Read compiled functions of script closures, that are reachable from root table,
+    /// and of functions declared inside of them, including anonymous ones
+    pub fn get_function_protos(&self) -> SqGetResult<Vec<SqFunctionProto>> {
+        let base = self.api().stack_top();
+        let mut visited = vec![];
+        let mut protos = vec![];
+        self.push_root_table();
+        self.collect_function_protos(&mut visited, &mut protos);
+        self.api().set_stack_top(base);
+
+        // Nested functions are not reachable through the api, only from protos of outer ones
+        let mut out = vec![];
+        let mut idx = 0;
+        while let Some(&ptr) = protos.get(idx) {
+            // SAFETY: protos are referenced by closures alive in root table or by their protos
+            let raw = unsafe { layout::function(ptr) }
+                .map_err(|e| SqVmError::other(e).into_stack_error("failed to read function"))?;
+            for nested in &raw.functions {
+                if !protos.contains(nested) {
+                    protos.push(*nested);
+                }
+            }
+
+            let mut calls = Vec::with_capacity(raw.calls.len());
+            for (name, line) in &raw.calls {
+                calls.push((self.get_handler_value(name)?, *line));
+            }
+
+            let mut lines = raw.lines;
+            lines.sort_unstable();
+            out.push(SqFunctionProto {
+                name: self.get_handler_value(&raw.name).ok(),
+                src: self.get_handler_value(&raw.sourcename).ok(),
+                lines,
+                calls,
+                indirect_calls: raw.indirect_calls,
+            });
+            idx += 1;
+        }
+
+        Ok(out)
+    }
+
+    /// Get value of object handler, that is not on the stack
+    fn get_handler_value<T>(&self, obj: &SQObject) -> SqGetResult<T> where Self: SqGet<T> {
+        self.push_stack_obj(obj);
+        let res = self.get(-1);
+        self.pop(1);
+        res
+    }
+
+    /// Collect protos of closures in container on top of the stack and in its children containers
+    fn collect_function_protos(&self, visited: &mut Vec<usize>, protos: &mut Vec<usize>) {
+        self.api().push_null();
+        while self.sq_iter_next(-2).is_some() {
+            match self.get_type(-1) {
+                SqType::Closure => if let Ok(ptr) = self.get_object_ptr(-1) {
+                    // SAFETY: closure is kept alive by the stack
+                    if let Ok(proto) = unsafe { layout::closure_function(ptr) } {
+                        if !protos.contains(&proto) {
+                            protos.push(proto);
+                        }
+                    }
+                }
+                SqType::Table | SqType::Class | SqType::Array => if let Ok(ptr) = self.get_object_ptr(-1) {
+                    if !visited.contains(&ptr) {
+                        visited.push(ptr);
+                        self.collect_function_protos(visited, protos);
+                    }
+                }
+                _ => (),
+            }
+            // Pop key and value
+            self.pop(2);
+        }
+        // Pop iterator
+        self.pop(1);
+    }
+
     /// Pops an object from the stack (must be a table, instance or class) clones
     /// the closure at position `idx` in the stack and sets the popped object
     /// as environment of the cloned closure.
@@ -505,6 +679,14 @@ impl<S> Vm<S> where S: safety::VmDrop {
         Ok(obj)
     }
 
+    /// Get identity of object at position `idx`: pointer stored in its handler.
+    /// Meaningful only for reference types, e.g. tables and arrays
+    pub fn get_object_ptr(&self, idx: isize) -> SqVmResult<usize> {
+        let obj = self.get_stack_obj(idx)?;
+        // Pointer variants of value union share first bytes
+        Ok(unsafe { std::ptr::read_unaligned(addr_of!(obj._unVal) as *const usize) })
+    }
+
     /// Push an object referenced by an object handler into the stack.
     #[inline]
     pub fn push_stack_obj(&self, obj: &SQObject) {
@@ -548,10 +730,22 @@ impl<S> Vm<S> where S: safety::VmDrop {
     /// In order to receive a `line` callback, is necessary 
     /// to compile the scripts with the line informations. 
     /// Without line informations activated, only the `call/return` callbacks will be invoked.
-    pub fn set_debug_hook<F>(&mut self, mut hook: F)
+    pub fn set_debug_hook<F>(&mut self, hook: F)
     where
         F: FnMut(DebugEventWithSrc, &Vm<safety::Friend>) + Send + 'static
     {
+        self.set_chained_debug_hook(None, hook)
+    }
+
+    /// Set VM debug hook like [Vm::set_debug_hook], but call `prev` hook first
+    /// with the same arguments, e.g. hook of host application taken by [Vm::get_debug_hook].
+    ///
+    /// Events raised by `prev` itself are not passed to any hook
+    pub fn set_chained_debug_hook<F>(&mut self, prev: Option<Arc<SqForeignHook>>, mut hook: F)
+    where
+        F: FnMut(DebugEventWithSrc, &Vm<safety::Friend>) + Send + 'static
+    {
+        let in_prev = Cell::new(false);
         let debug_hook_glue = sq_closure!(
             #[(vm_var = "vm", outer_crate = "crate")]
             move |
@@ -560,6 +754,26 @@ impl<S> Vm<S> where S: safety::VmDrop {
             line: isize,
             funcname: Option<String>
             | {
+                if in_prev.get() {
+                    return;
+                }
+
+                if let Some(prev) = &prev {
+                    // Vm calls hooks with root table as `this`
+                    in_prev.set(true);
+                    vm.push_stack_obj(&prev.obj);
+                    vm.push_root_table();
+                    vm.push(event_type);
+                    vm.push(src.clone());
+                    vm.push(line);
+                    vm.push(funcname.clone());
+                    if let Err(e) = vm.call_closure_api(5, false, false) {
+                        warn!("chained debug hook failed: {e}");
+                    }
+                    vm.pop(1);
+                    in_prev.set(false);
+                }
+
                 let line_opt = if line > 0 { Some(line) } else { None };
 
                 let event = match char::from_u32(event_type as u32).unwrap() {
@@ -580,6 +794,72 @@ impl<S> Vm<S> where S: safety::VmDrop {
         }
     }
 
+    /// Get debug hook set on vm, e.g. by host application.
+    ///
+    /// Api can't read hook back, so it is read from vm internals.
+    /// Unknown vm layout is an error
+    pub fn get_debug_hook(&self) -> SqVmResult<Option<SqForeignHook>> {
+        // Root table is known through the api, so it validates layout
+        self.push_root_table();
+        let root_table = self.get_object_ptr(-1);
+        self.pop(1);
+
+        let mut obj = unsafe { layout::debug_hook(self.api().handle() as usize, root_table?) }
+            .map_err(SqVmError::other)?;
+        if obj._type == api::tagSQObjectType_OT_NULL {
+            return Ok(None);
+        }
+
+        self.inc_ref(&mut obj);
+        Ok(Some(SqForeignHook { obj, vm: self.api().handle() }))
+    }
+
+    /// Set hook taken by [Vm::get_debug_hook] back, e.g. when debugger is detached
+    pub fn restore_debug_hook(&mut self, hook: &SqForeignHook) {
+        self.push_stack_obj(&hook.obj);
+        unsafe {
+            self.api().setdebughook();
+        }
+    }
+
+    /// Disable VM debug hook, releasing its closure
+    pub fn remove_debug_hook(&mut self) {
+        self.api().push_null();
+        unsafe {
+            self.api().setdebughook();
+        }
+    }
+
+    /// Set VM error handler that will be called with thrown error value
+    /// every time an error is raised, before the stack is unwound.
+    ///
+    /// Replaces previously set error handler, as the api does not expose it
+    pub fn set_error_handler<F>(&mut self, mut handler: F)
+    where
+        F: FnMut(DynSqVar, &Vm<safety::Friend>) + Send + 'static
+    {
+        let error_handler_glue = sq_closure!(
+            #[(vm_var = "vm", outer_crate = "crate")]
+            move |error: DynSqVar| {
+                handler(error, vm);
+            }
+        );
+
+        <Self as SqPush<SqBoxedClosure>>::push(self, error_handler_glue);
+
+        unsafe {
+            self.api().seterrorhandler();
+        }
+    }
+
+    /// Remove VM error handler, releasing its closure
+    pub fn remove_error_handler(&mut self) {
+        self.api().push_null();
+        unsafe {
+            self.api().seterrorhandler();
+        }
+    }
+
     /// The member 'func_id' of the returned SqFunctionInfo structure is a
     /// unique identifier of the function; this can be useful to identify
     /// a specific piece of squirrel code in an application like for instance a profiler.
@@ -637,14 +917,24 @@ impl<S> Vm<S> where S: safety::VmDrop {
         let ptr = unsafe { self.api().getlocal(level, idx) };
         if ptr != 0 as _ {
             let name = unsafe { cstr_to_string(ptr) };
-            let val = self.get_constrain(-1, max_depth)?;
+            // Pushed local is popped even if it can't be read
+            let val = self.get_constrain(-1, max_depth);
             self.pop(1);
-            Ok(Some(SqLocalVar{ name, val }))
+            Ok(Some(SqLocalVar{ name, val: val? }))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns all locals of stackframe at `level`, each read once, in order of [Vm::get_local].
+    ///
+    /// Stops on first missing or unreadable local
+    pub fn get_frame_locals(&self, level: usize, max_depth: Option<usize>) -> Vec<SqLocalVar> {
+        (0..)
+            .map_while(|idx| self.get_local(level, idx, max_depth).ok().flatten())
+            .collect()
+    }
+
     /// Returns the name and handle of a local variable given
     /// stackframe and sequence in the stack.
     ///
@@ -668,8 +958,272 @@ impl<S> Vm<S> where S: safety::VmDrop {
         }
     }
 
+    /// Get value of local variable at call stack `level` or of its child.
+    ///
+    /// `path` starts with local name, following keys are used to get slots of containers,
+    /// so only containers on the path are touched and only target is expanded.
+    /// Slots are read raw, script metamethods and delegates are not invoked.
+    /// Negative [SqPathKey::Index] of arrays counts from the end.
+    /// Last key may be [SqPathKey::Slice] of array
+    pub fn get_local_path(
+        &self,
+        level: usize,
+        path: &[SqPathKey],
+        max_depth: Option<usize>
+    ) -> SqGetResult<DynSqVar> {
+        self.get_path(path, max_depth, |path| self.push_local_path(level, path))
+    }
+
+    /// Get value of global variable or of its child.
+    ///
+    /// `path` contains keys of root table and its children, 
+    /// see [Vm::get_local_path] for their format
+    pub fn get_global_path(&self, path: &[SqPathKey], max_depth: Option<usize>) -> SqGetResult<DynSqVar> {
+        self.get_path(path, max_depth, |keys| {
+            self.push_root_table();
+            self.push_path_keys(keys)
+        })
+    }
+
+    /// Get target of `path`, which is pushed by `push_path`, or slice of array before it
+    fn get_path(
+        &self,
+        path: &[SqPathKey],
+        max_depth: Option<usize>,
+        push_path: impl FnOnce(&[SqPathKey]) -> SqGetResult<()>
+    ) -> SqGetResult<DynSqVar> {
+        let (path, slice) = match path.split_last() {
+            Some((SqPathKey::Slice(slice), container)) => (container, Some(slice)),
+            _ => (path, None),
+        };
+
+        let base = self.api().stack_top();
+        let res = push_path(path).and_then(|_| match slice {
+            Some(slice) => self.get_array_slice(slice, max_depth),
+            None => self.get_constrain(-1, max_depth),
+        });
+
+        // Pop path containers
+        self.api().set_stack_top(base);
+        res
+    }
+
+    /// Get raw object of local variable at call stack `level` or of its child.
+    ///
+    /// See [Vm::get_local_path] for `path` format
+    pub fn get_local_raw(&self, level: usize, path: &[SqPathKey]) -> SqGetResult<SqRawObject> {
+        let base = self.api().stack_top();
+        let res = self.push_local_path(level, path)
+            .and_then(|_| self.get_raw_object(-1));
+
+        // Pop local and its children
+        self.api().set_stack_top(base);
+        res
+    }
+
+    /// Set scalar member of container, reached from local variable at call stack `level`.
+    ///
+    /// Sqvm api can't set locals themselves, so `path` must have at least one key
+    /// after local name. See [Vm::get_local_path] for `path` format
+    pub fn set_local_path(&self, level: usize, path: &[SqPathKey], value: DynSqVar) -> SqGetResult<()> {
+        let Some((key, container)) = path.split_last().filter(|(_, c)| !c.is_empty()) else {
+            return Err(SqVmError::other("only members of containers can be set, not locals")
+                .into_stack_error("failed to set local"))
+        };
+        let push_key = || {
+            match key {
+                SqPathKey::Name(name) => self.push(name.as_str()),
+                SqPathKey::Index(idx) => self.push(*idx),
+                SqPathKey::Slice(_) => return Err(SqVmError::other("slice can't be set")
+                    .into_stack_error("failed to set local")),
+            }
+            Ok(())
+        };
+
+        let base = self.api().stack_top();
+        let res = self.push_local_path(level, container).and_then(|_| {
+            // Check old value
+            push_key()?;
+            self.slot_get_raw(-2)
+                .map_err(|e| e.into_stack_error("failed to get slot"))?;
+            if self.get_type(-1).is_complex() {
+                return Err(SqVmError::other("target is not a scalar")
+                    .into_stack_error("failed to set local"))
+            }
+            self.pop(1);
+
+            push_key()?;
+            self.push(value)?;
+            self.slot_set_raw(-3)
+                .map_err(|e| e.into_stack_error("failed to set slot"))
+        });
+
+        // Pop local and its children
+        self.api().set_stack_top(base);
+        res
+    }
+
+    /// Push local and containers on `path` to it's target
+    fn push_local_path(&self, level: usize, path: &[SqPathKey]) -> SqGetResult<()> {
+        let Some((SqPathKey::Name(local), keys)) = path.split_first() else {
+            return Err(SqVmError::other("path must start with local name")
+                .into_stack_error("failed to get local"))
+        };
+
+        let mut idx = 0;
+        loop {
+            let ptr = unsafe { self.api().getlocal(level, idx) };
+            if ptr == 0 as _ {
+                return Err(SqVmError::other(format!("local `{local}` not found"))
+                    .into_stack_error("failed to get local"))
+            }
+
+            if unsafe { cstr_to_string(ptr) } == *local {
+                break
+            }
+            self.pop(1);
+            idx += 1;
+        }
+
+        self.push_path_keys(keys)
+    }
+
+    /// Push children of container on top of the stack by `keys`, each of previous one
+    fn push_path_keys(&self, keys: &[SqPathKey]) -> SqGetResult<()> {
+        for key in keys {
+            match key {
+                SqPathKey::Index(idx) if self.get_type(-1) == SqType::Array => {
+                    let len = self.get_size(-1)
+                        .map_err(|e| e.into_stack_error("failed to get array size"))?;
+                    self.push(Self::array_index(*idx, len)?);
+                }
+                SqPathKey::Index(idx) => self.push(*idx),
+                SqPathKey::Name(name) => self.push(name.as_str()),
+                SqPathKey::Slice(_) => return Err(SqVmError::other(format!("slice `{key}` must be the last key"))
+                    .into_stack_error("failed to get slot")),
+            };
+            self.slot_get_raw(-2)
+                .map_err(|e| e.into_stack_error("failed to get slot"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolve array index, negative one counts from the end
+    fn array_index(num: isize, len: isize) -> SqGetResult<isize> {
+        let idx = if num < 0 { len + num } else { num };
+        if !(0..len).contains(&idx) {
+            return Err(SqVmError::other(format!("index {num} is out of range for array of length {len}"))
+                .into_stack_error("failed to get slot"))
+        }
+        Ok(idx)
+    }
+
+    /// Get elements of array on top of the stack in range `start:end`.
+    /// Bounds may be negative to count from the end or omitted
+    fn get_array_slice(&self, slice: &str, max_depth: Option<usize>) -> SqGetResult<DynSqVar> {
+        if self.get_type(-1) != SqType::Array {
+            return Err(SqVmError::other(format!("slice `{slice}` of non-array"))
+                .into_stack_error("failed to get slice"))
+        }
+
+        let len = self.get_size(-1)
+            .map_err(|e| e.into_stack_error("failed to get array size"))?;
+        let (start, end) = slice.split_once(':').unwrap();
+        let parse_bound = |bound: &str, default| match bound {
+            "" => Ok(default),
+            bound => bound.parse::<isize>()
+                .map(|b| if b < 0 { len + b } else { b })
+                .map_err(|_| SqVmError::other(format!("invalid slice bound `{bound}`"))
+                    .into_stack_error("failed to get slice")),
+        };
+        let (start, end) = (parse_bound(start, 0)?, parse_bound(end, len)?);
+        if start < 0 || end > len || start > end {
+            return Err(SqVmError::other(format!("slice `{slice}` is out of range for array of length {len}"))
+                .into_stack_error("failed to get slice"))
+        }
+
+        Ok(match self.get_constrain(-1, max_depth)? {
+            DynSqVar::Array(v) => DynSqVar::Array(v[start as usize..end as usize].to_vec()),
+            other => other,
+        })
+    }
+
+    fn get_raw_object(&self, idx: isize) -> SqGetResult<SqRawObject> {
+        let obj = self.get_stack_obj(idx)
+            .map_err(|e| e.into_stack_error("failed to get stack object"))?;
+
+        // Read-only view of handler itself, referenced object is not touched
+        let bytes = unsafe {
+            std::slice::from_raw_parts(addr_of!(obj) as *const u8, std::mem::size_of::<SQObject>())
+        }.to_vec();
+
+        Ok(SqRawObject { tag: obj._type, bytes, val: self.get_constrain(idx, Some(0))? })
+    }
+
+    /// Get info of closure executed at call stack `level`.
+    ///
+    /// There is no api to get closure of a call frame directly, so closure is
+    /// searched by function name in frame's `this` and then in the root table.
+    /// Anonymous closures or closures stored elsewhere cannot be found.
+    pub fn get_frame_closure(&self, level: usize) -> SqGetResult<DynSqVar> {
+        let SqStackInfo { funcname, .. } = self.get_stack_info(level)
+            .map_err(|e| e.into_stack_error("failed to get stack info"))?;
+
+        let Some(name) = funcname else {
+            return Err(SqVmError::other("function is anonymous")
+                .into_stack_error("failed to find frame closure"))
+        };
+
+        let mut idx = 0;
+        let this = loop {
+            match self.get_local_handle(level, idx)? {
+                Some(SqLocalVarHandle { name, handle }) if name == "this" => break Some(handle),
+                Some(_) => idx += 1,
+                None => break None,
+            }
+        };
+
+        let base = self.api().stack_top();
+
+        // Push containers to search in
+        self.push_root_table();
+        if let Some(this) = this {
+            self.push(this);
+        }
+
+        // Search from stack top (`this`) down to the root table 
+        let found = loop {
+            if self.api().stack_top() == base {
+                break false
+            }
+
+            self.push(name.as_str());
+            if self.slot_get(-2).is_ok() {
+                if self.get_type(-1).is_closure() {
+                    break true
+                }
+                self.pop(1);
+            }
+
+            // Pop searched container
+            self.pop(1);
+        };
+
+        if !found {
+            return Err(SqVmError::other(format!("closure `{name}` not found"))
+                .into_stack_error("failed to find frame closure"))
+        }
+
+        let info = self.get_constrain(-1, Some(1));
+
+        // Pop closure and containers left
+        self.api().set_stack_top(base);
+        info
+    }
+
     // TODO: Add typemask
-    
+
     /// Bind rust native function to root table of SQVM
     pub fn register_function(&self, name: &str, func: SqFunction) {
         self.push_root_table();
@@ -768,6 +1322,8 @@ impl Vm<safety::Safe> {
         let handle = VmApi::open(initial_stack_size);
         Self {
             api: VmApi(handle),
+            expanding: RefCell::default(),
+            upvalues: Cell::default(),
             _safety: safety::Safe
         }
     }
@@ -786,6 +1342,8 @@ impl Vm<safety::Unsafe> {
     pub unsafe fn from_handle(handle: api::HSQUIRRELVM) -> Self {
         Self {
             api: VmApi(handle),
+            expanding: RefCell::default(),
+            upvalues: Cell::default(),
             _safety: safety::Unsafe,
         }
     }
@@ -798,6 +1356,8 @@ impl Vm<safety::Unsafe> {
         std::mem::forget(self);
         Vm {
             api: VmApi(handle),
+            expanding: RefCell::default(),
+            upvalues: Cell::default(),
             _safety: safety::Safe,
         }
     }
@@ -811,6 +1371,8 @@ impl Vm<safety::Unsafe> {
         std::mem::forget(self);
         Vm {
             api: VmApi(handle),
+            expanding: RefCell::default(),
+            upvalues: Cell::default(),
             _safety: safety::Friend,
         }
     }
@@ -4,6 +4,8 @@ mod sq;
 
 /// Creates a struct with associated function and it's squirrel wrapper
 /// (`::rust_fn` and `::sq_fn`)
+///
+/// If function returns `Result`, `Ok` value is pushed and `Err` is thrown to vm
 #[proc_macro_attribute]
 pub fn sqfn(
     args: TokenStream,
@@ -4,7 +4,7 @@ use darling::{FromMeta, ToTokens};
 use syn::{
     AttributeArgs, ItemFn, parse_macro_input, Ident, Visibility, FnArg, Pat, Path,
     ExprClosure, Type, NestedMeta, parse::{Parse, ParseStream}, Token,
-    bracketed, parenthesized, parse_quote
+    bracketed, parenthesized, parse_quote, PathArguments, GenericArgument
 };
 
 const OUTER_CRATE: &str = "sq_common";
@@ -194,6 +194,24 @@ pub fn sqfn_impl(
         }
     };
 
+    // `Result` return types are unwrapped, `Err` is thrown to vm
+    let is_result = ret_type.iter().any(|t| matches!(&**t,
+        Type::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "Result")
+    ));
+
+    // `Result<(), E>` returns nothing, as unit can't be pushed
+    let is_unit_result = is_result && ret_type.iter().any(|t| matches!(&**t,
+        Type::Path(p) if p.path.segments.last().is_some_and(|s| matches!(&s.arguments,
+            PathArguments::AngleBracketed(a) if matches!(a.args.first(),
+                Some(GenericArgument::Type(Type::Tuple(t))) if t.elems.is_empty()
+            )
+        ))
+    ));
+    let (ret_type, ret_pat) = match is_unit_result {
+        true => (vec![], quote! { () }),
+        false => (ret_type, quote! { ret }),
+    };
+
     let (vm_ident, vm_option) = match args.vm_var {
         Some(ident) => {
             let arg = parse_quote!{ #ident: &#outer::Vm<#outer::safety::Friend> };
@@ -271,7 +289,19 @@ pub fn sqfn_impl(
 
     // Here function called
 
+    let unwrap_result = is_result.then(|| quote! {
+        let #ret_pat = match ret {
+            Ok(ret) => ret,
+            Err(e) => {
+                #vm_ident.throw_error(e.to_string());
+                return -1;
+            }
+        };
+    });
+
     let sq_fn_body_end = quote! {
+        #unwrap_result
+
         // if return type exists, push it and return 1
         #( 
             let _: #ret_type;